@@ -4,9 +4,9 @@ import argparse
 import struct
 import logging
 
-N_BLOCKS = 20  # 00
+N_BLOCKS = 2000
 BLOCK_SIZE = 4096
-MAGIC = b'MORA'
+NAME_LENGTH = 256
 
 
 def p32(val):
@@ -21,6 +21,13 @@ def p8(val):
     return struct.pack('<B', val)
 
 
+def encode_name(name):
+    name = name.encode('utf-8')
+    if len(name) >= NAME_LENGTH:
+        raise Exception('{} is too long'.format(name))
+    return name + b'\x00' * (NAME_LENGTH - len(name))
+
+
 def st_mode2mode(m):
     return 0
 
@@ -40,8 +47,8 @@ def read_exact(f, size):
 
 
 # type
-DIRECTORY_TYPE = 1
-FILE_TYPE = 2
+FILE_TYPE = 1
+DIRECTORY_TYPE = 2
 
 
 class SuperBlock:
@@ -53,7 +60,6 @@ class SuperBlock:
 
     def write(self, image):
         block = b''
-        block += MAGIC
         block += p32(self.root_index)
         block += p32(self.management_index)
         block += p32(self.n_blocks)
@@ -99,7 +105,7 @@ Directory data
 --------------
 type: u8
 dummy: u8
-name: char[256]. ends with \x00 if length is less than 256
+name: char[256]. must end with \x00
 permission: u16
 owner: u16
 group: u16
@@ -126,7 +132,7 @@ class Directory(Data):
         block = b''
         block += p8(DIRECTORY_TYPE)
         block += p8(0)
-        block += self.name.encode('utf-8')
+        block += encode_name(self.name)
         block += p16(self.permission)
         block += p16(self.owner)
         block += p16(self.group)
@@ -160,6 +166,7 @@ class Directory(Data):
                 tmp = b''
                 count = 0
             tmp += p32(index)
+            count += 1
         if count != 0:
             indirect_blocks.append(image.write_block(tmp))
 
@@ -182,7 +189,7 @@ class File(Data):
         block = b''
         block += p8(FILE_TYPE)
         block += p8(0)
-        block += self.name.encode('utf-8')
+        block += encode_name(self.name)
         block += p16(self.permission)
         block += p16(self.owner)
         block += p16(self.group)
@@ -212,6 +219,7 @@ class File(Data):
                     tmp = b''
                     count = 0
                 tmp += p32(index)
+                count += 1
             if count != 0:
                 indirect_blocks.append(image.write_block(tmp))
 
@@ -12,7 +12,7 @@ pub struct DirectoryRaw {
     files: [u32; N_POINTER_PER_DIR],
 }
 
-#[derive(Clone)]
+#[derive(Copy, Clone)]
 pub struct Directory {
     id: Id,
 }
@@ -35,6 +35,9 @@ impl Index {
     fn to_block_id(&self) -> usize {
         self.0 * (BLOCKSIZE / 4) + self.1
     }
+    fn next(&self) -> Index {
+        Index::from_block_id(self.to_block_id() + 1)
+    }
     #[allow(dead_code)]
     fn from_pointer(p: u32) -> Index {
         let id = p as usize / BLOCKSIZE;
@@ -53,24 +56,24 @@ impl Directory {
     ) -> Result<Directory, FileError> {
         let id = bm.alloc_block()?;
         let directory = Directory { id };
-        let meta_block = directory.get_meta_block(bm)?;
+        let mut meta_block = directory.get_meta_block(bm)?;
         meta_block.name = name;
         meta_block.permission = permission.bits();
         meta_block.ty = Type::Directory.to_repr();
         meta_block.file_count = 0;
-        directory.write_meta_block(bm, meta_block)?;
+        directory.write_meta_block(bm, &meta_block)?;
         // TBD: owner/ group
         Ok(directory)
     }
 
     fn find_free_block(&self, bm: &mut BlockManager) -> Result<Index, FileError> {
-        let meta_block = self.get_meta_block(bm)?;
+        let mut meta_block = self.get_meta_block(bm)?;
         for i in 0..N_POINTER_PER_DIR {
             let id = meta_block.files[i];
             if id == 0 {
                 let id = bm.alloc_block()?;
                 meta_block.files[i] = id.0;
-                self.write_meta_block(bm, meta_block)?;
+                self.write_meta_block(bm, &meta_block)?;
                 return Ok(Index(i, 0));
             }
             let mut block = bm.read_block(Id(id))?;
@@ -140,21 +143,23 @@ impl Directory {
     }
 
     pub fn add_file(&mut self, bm: &mut BlockManager, id: Id) -> Result<(), FileError> {
-        let meta_block = self.get_meta_block(bm)?;
         let entry = self.find_free_block(bm)?;
 
         self.write_entry(bm, entry, id)?;
 
+        // find_free_block may have updated the meta block
+        let mut meta_block = self.get_meta_block(bm)?;
         meta_block.file_count += 1;
-        self.write_meta_block(bm, meta_block)?;
+        self.write_meta_block(bm, &meta_block)?;
         Ok(())
     }
 
     pub fn remove_file(&mut self, bm: &mut BlockManager, id: Id) -> Result<(), FileError> {
-        let meta_block = self.get_meta_block(bm)?;
         let index = self.find_id(bm, id)?;
         self.remove_entry(bm, index)?;
-        self.write_meta_block(bm, meta_block)?;
+        let mut meta_block = self.get_meta_block(bm)?;
+        meta_block.file_count -= 1;
+        self.write_meta_block(bm, &meta_block)?;
         Ok(())
     }
 
@@ -175,7 +180,7 @@ impl Directory {
         loop {
             match self.find_next_block(bm, index)? {
                 Some((i, _)) if cnt < n => {
-                    index = i;
+                    index = i.next();
                     cnt += 1;
                 }
                 Some((_, id)) => {
@@ -198,7 +203,7 @@ impl Directory {
         'outer: loop {
             match self.find_next_block(bm, index)? {
                 Some((next_index, id)) => {
-                    index = next_index;
+                    index = next_index.next();
                     let file = File::get_file(bm, id)?;
                     for i in 0..256 {
                         if path[i] != file.name[i] {
@@ -215,6 +220,7 @@ impl Directory {
                             continue 'outer;
                         }
                         if path[i] == 0 {
+                            path.countup(i);
                             return Ok(Some((id, path)));
                         }
                     }
@@ -38,6 +38,45 @@ impl<'a> FileSystem<'a> {
         self.search_inner(path, self.root.clone())
     }
 
+    // create an empty regular file in an existing directory
+    pub fn create(&mut self, name: &[u8], permission: Flag) -> Result<File, FileError> {
+        match self.search(name) {
+            Ok(_) => return Err(FileError::AlreadyExists),
+            Err(FileError::NotFound) => (),
+            Err(e) => return Err(e),
+        }
+        let len = match name.iter().position(|&c| c == 0) {
+            Some(len) => len,
+            None => return Err(FileError::IllegalPath),
+        };
+        // search has checked that name starts with '/'
+        let slash = name[..len].iter().rposition(|&c| c == b'/').unwrap();
+        let basename = &name[slash + 1..len];
+        if basename.len() == 0 || basename.len() >= 256 || slash >= 256 {
+            return Err(FileError::IllegalPath);
+        }
+
+        let mut dir = if slash == 0 {
+            self.root
+        } else {
+            let mut parent = [0u8; 256];
+            parent[..slash].copy_from_slice(&name[..slash]);
+            match self.search(&parent)? {
+                File::Direcotry(dir) => dir,
+                File::Regular(_) => return Err(FileError::IllegalType),
+            }
+        };
+
+        let mut file_name = [0u8; 256];
+        file_name[..basename.len()].copy_from_slice(basename);
+        let file = regular::Regular::create(self.block_manager, file_name, permission)?;
+        if let Err(e) = dir.add_file(self.block_manager, file.my_id()) {
+            self.block_manager.free_block(file.my_id())?;
+            return Err(e);
+        }
+        Ok(File::Regular(file))
+    }
+
     pub fn read(&mut self, file: &mut File, buf: &mut [u8], size: usize) -> Result<(), FileError> {
         match file {
             File::Regular(ref mut r) => r.read(self.block_manager, buf, size),
@@ -52,6 +91,27 @@ impl<'a> FileSystem<'a> {
         }
     }
 
+    pub fn size(&mut self, file: &File) -> Result<usize, FileError> {
+        match file {
+            File::Regular(ref r) => r.size(self.block_manager),
+            _ => Err(FileError::IllegalType),
+        }
+    }
+
+    pub fn truncate(&mut self, file: &mut File) -> Result<(), FileError> {
+        match file {
+            File::Regular(ref mut r) => r.truncate(self.block_manager),
+            _ => Err(FileError::IllegalType),
+        }
+    }
+
+    pub fn tell(&self, file: &File) -> Result<usize, FileError> {
+        match file {
+            File::Regular(ref r) => Ok(r.tell()),
+            _ => Err(FileError::IllegalType),
+        }
+    }
+
     pub fn seek(&mut self, file: &mut File, offset: i32) -> Result<(), FileError> {
         match file {
             File::Regular(ref mut r) => r.seek(self.block_manager, offset),
@@ -59,3 +119,84 @@ impl<'a> FileSystem<'a> {
         }
     }
 }
+
+// run f on a file system of 16 blocks which has the root and /tmp directories
+#[cfg(test)]
+fn with_fs<F: FnOnce(&mut FileSystem)>(f: F) {
+    use crate::hardware::memory_block_manager::MemoryBlockManager;
+    const N: usize = 16;
+
+    let mut image = [0u32; BLOCKSIZE / 4 * N];
+    let data = unsafe { slice::from_raw_parts_mut(image.as_mut_ptr() as *mut u8, BLOCKSIZE * N) };
+    let mut bm = MemoryBlockManager::new(data);
+    bm.super_block().n_blocks = N as u32;
+    bm.mark_as_used(Id(0));
+    bm.mark_as_used(Id(1));
+
+    let mut name = [0u8; 256];
+    name[..4].copy_from_slice(b"root");
+    let mut root = dir::Directory::create(&mut bm, name, Flag::USER_READ).unwrap();
+    bm.super_block().root_directory_index = root.my_id().0;
+    let mut name = [0u8; 256];
+    name[..3].copy_from_slice(b"tmp");
+    let tmp = dir::Directory::create(&mut bm, name, Flag::USER_READ).unwrap();
+    root.add_file(&mut bm, tmp.my_id()).unwrap();
+
+    f(&mut FileSystem::new(&mut bm));
+}
+
+#[test]
+fn test_search_and_read() {
+    with_fs(|fs| {
+        let mut file = fs.create(b"/hello\x00", Flag::USER_READ).unwrap();
+        fs.write(&mut file, b"hello world", 11).unwrap();
+
+        let mut file = fs.search(b"/hello\x00").unwrap();
+        assert_eq!(fs.size(&file).unwrap(), 11);
+        let mut buf = [0u8; 11];
+        fs.read(&mut file, &mut buf, 11).unwrap();
+        assert_eq!(&buf, b"hello world");
+        assert!(fs.search(b"/world\x00").is_err());
+    });
+}
+
+#[test]
+fn test_create() {
+    with_fs(|fs| {
+        let mut file = fs.create(b"/tmp/core\x00", Flag::USER_READ).unwrap();
+        fs.write(&mut file, b"core", 4).unwrap();
+
+        let mut file = fs.search(b"/tmp/core\x00").unwrap();
+        let mut buf = [0u8; 4];
+        fs.read(&mut file, &mut buf, 4).unwrap();
+        assert_eq!(&buf, b"core");
+
+        match fs.create(b"/tmp/core\x00", Flag::USER_READ) {
+            Err(FileError::AlreadyExists) => (),
+            _ => panic!("created the same file twice"),
+        }
+        assert!(fs.create(b"/none/core\x00", Flag::USER_READ).is_err());
+        assert!(fs.create(b"/tmp/\x00", Flag::USER_READ).is_err());
+    });
+}
+
+#[test]
+fn test_truncate() {
+    with_fs(|fs| {
+        let mut file = fs.create(b"/core\x00", Flag::USER_READ).unwrap();
+        let data = [b'x'; BLOCKSIZE * 3];
+        // the blocks are freed, or the disk runs out of them
+        for _ in 0..4 {
+            fs.truncate(&mut file).unwrap();
+            fs.write(&mut file, &data, data.len()).unwrap();
+        }
+        fs.truncate(&mut file).unwrap();
+        fs.write(&mut file, b"core", 4).unwrap();
+
+        let mut file = fs.search(b"/core\x00").unwrap();
+        assert_eq!(fs.size(&file).unwrap(), 4);
+        let mut buf = [0u8; 4];
+        fs.read(&mut file, &mut buf, 4).unwrap();
+        assert_eq!(&buf, b"core");
+    });
+}
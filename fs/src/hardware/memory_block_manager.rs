@@ -1,11 +1,13 @@
 use crate::*;
 
+// block manager over a disk image placed on memory
+// (data must be aligned to 4 bytes and its length must be multiple of BLOCKSIZE)
 pub struct MemoryBlockManager<'a> {
-    data: &'a mut [u8; BLOCKSIZE * N_BLOCKS],
+    data: &'a mut [u8],
 }
 
 impl<'a> MemoryBlockManager<'a> {
-    pub fn new(data: &'a mut [u8; BLOCKSIZE * N_BLOCKS]) -> MemoryBlockManager<'a> {
+    pub fn new(data: &'a mut [u8]) -> MemoryBlockManager<'a> {
         MemoryBlockManager { data }
     }
 }
@@ -27,7 +29,7 @@ impl<'a> BlockManager<'a> for MemoryBlockManager<'a> {
     fn read_block(&mut self, id: Id) -> Result<Block, FileError> {
         id.check_is_not_super()?;
         self.valid_or_err(id)?;
-        let mut block = [0u8; BLOCKSIZE];
+        let mut block = Block::zero();
         for i in 0..BLOCKSIZE {
             block[i] = self.data[id.0 as usize * BLOCKSIZE + i];
         }
@@ -49,7 +51,7 @@ impl<'a> BlockManager<'a> for MemoryBlockManager<'a> {
         let j = k % 8;
         let index = BLOCKSIZE + i as usize;
         let x = (self.data[index] >> j) & 1;
-        x == 0
+        x == 1
     }
 
     fn mark_as_used(&mut self, id: Id) {
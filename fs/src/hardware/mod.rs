@@ -1 +1 @@
-pub mod memory_block_manager;
\ No newline at end of file
+pub mod memory_block_manager;
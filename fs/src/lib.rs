@@ -10,10 +10,10 @@ pub mod filesystem;
 pub mod hardware;
 pub mod regular;
 
+use core::ptr;
 use core::slice;
 
 const BLOCKSIZE: usize = 4096;
-const N_BLOCKS: usize = 100000;
 const N_POINTER_PER_DIR: usize = (BLOCKSIZE / 4) - 256 / 4 - 1 - 1 - 1;
 const N_POINTER_PER_FILE: usize = (BLOCKSIZE / 4) - 256 / 4 - 1 - 1 - 1;
 const N_DUMMIES: usize = (BLOCKSIZE / 4) - 256 / 4 - 1 - 1;
@@ -30,6 +30,7 @@ pub enum FileError {
     NotFound,
     IllegalPath,
     IllegalType,
+    AlreadyExists,
 }
 
 bitflags! {
@@ -45,7 +46,28 @@ bitflags! {
 
 type TypeRepr = u8;
 
-type Block = [u8; BLOCKSIZE];
+// aligned in order to be viewed as a table of u32
+#[repr(C, align(4))]
+pub struct Block([u8; BLOCKSIZE]);
+
+impl Block {
+    pub fn zero() -> Block {
+        Block([0u8; BLOCKSIZE])
+    }
+}
+
+impl core::ops::Deref for Block {
+    type Target = [u8; BLOCKSIZE];
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl core::ops::DerefMut for Block {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
 
 // this should be removed
 fn as_table_mut<'a>(block: &'a mut Block) -> &'a mut [u32] {
@@ -110,7 +132,7 @@ pub trait BlockManager<'a> {
     fn write_data(&mut self, id: Id, data: &[u8], offset: u32, size: u32) -> Result<(), FileError>;
 
     fn write_block(&mut self, id: Id, block: Block) -> Result<(), FileError> {
-        self.write_data(id, &block, 0, BLOCKSIZE as u32)
+        self.write_data(id, &block[..], 0, BLOCKSIZE as u32)
     }
 
     fn alloc_block(&mut self) -> Result<Id, FileError> {
@@ -160,6 +182,7 @@ impl Id {
     }
 }
 
+#[derive(Copy, Clone)]
 pub enum File {
     Regular(regular::Regular),
     Direcotry(dir::Directory),
@@ -202,12 +225,13 @@ pub trait FileLike {
 
     fn my_id(&self) -> Id;
 
-    fn get_file<'a>(bm: &mut BlockManager, id: Id) -> Result<&'a mut Self::Raw, FileError> {
-        let block = &mut bm.read_block(id)?;
-        Ok(unsafe { &mut *(block.as_mut_ptr() as *mut Self::Raw) })
+    // returns a copy of the meta block. modifications must be written back by write_meta_block
+    fn get_file(bm: &mut BlockManager, id: Id) -> Result<Self::Raw, FileError> {
+        let block = bm.read_block(id)?;
+        Ok(unsafe { ptr::read_unaligned(block.as_ptr() as *const Self::Raw) })
     }
 
-    fn get_meta_block<'a>(&self, bm: &mut BlockManager) -> Result<&'a mut Self::Raw, FileError> {
+    fn get_meta_block(&self, bm: &mut BlockManager) -> Result<Self::Raw, FileError> {
         Self::get_file(bm, self.my_id())
     }
     fn write_meta_block(
@@ -219,7 +243,7 @@ pub trait FileLike {
             &*(slice::from_raw_parts((meta_block as *const Self::Raw) as *const u8, BLOCKSIZE))
         };
         // hmm.. this translation should be removed.
-        let mut fixed = [0u8; BLOCKSIZE];
+        let mut fixed = Block::zero();
         for i in 0..BLOCKSIZE {
             fixed[i] = meta_block[i];
         }
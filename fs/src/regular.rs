@@ -26,6 +26,7 @@ pub struct RegularRaw {
     data: [u32; N_POINTER_PER_FILE],
 }
 
+#[derive(Copy, Clone)]
 pub struct Regular {
     pub id: Id,
     pointer: u32,
@@ -67,10 +68,6 @@ impl Regular {
     fn get_current_block_id(&self, bm: &mut BlockManager) -> Result<Id, FileError> {
         Ok(Id(self.get_table_entry(bm)?))
     }
-    fn write_current_block(&self, bm: &mut BlockManager, block: Block) -> Result<(), FileError> {
-        let id = self.get_current_block_id(bm)?;
-        bm.write_block(id, block)
-    }
     fn get_current_block(&self, bm: &mut BlockManager) -> Result<Block, FileError> {
         let id = self.get_current_block_id(bm)?;
         bm.read_block(id)
@@ -82,12 +79,12 @@ impl Regular {
     ) -> Result<Regular, FileError> {
         let id = bm.alloc_block()?;
         let regular = Regular { id, pointer: 0 };
-        let meta_block = regular.get_meta_block(bm)?;
+        let mut meta_block = regular.get_meta_block(bm)?;
         meta_block.name = name;
         meta_block.permission = permission.bits();
         meta_block.ty = Type::Regular.to_repr();
         meta_block.size = 0;
-        regular.write_meta_block(bm, meta_block)?;
+        regular.write_meta_block(bm, &meta_block)?;
         // TBD: owner/ group
         Ok(regular)
     }
@@ -106,8 +103,14 @@ impl Regular {
 
     fn alloc_block(&self, bm: &mut BlockManager, index: Index) -> Result<(), FileError> {
         let allocated = bm.alloc_block()?;
-        let meta_block = self.get_meta_block(bm)?;
-        let id = meta_block.data[index.0];
+        let mut meta_block = self.get_meta_block(bm)?;
+        let mut id = meta_block.data[index.0];
+        if id == 0 {
+            // the indirect table is not allocated yet
+            id = bm.alloc_block()?.0;
+            meta_block.data[index.0] = id;
+            self.write_meta_block(bm, &meta_block)?;
+        }
         let mut block = bm.read_block(Id(id))?;
         let table = as_table_mut(&mut block);
         table[index.1] = allocated.0;
@@ -121,8 +124,8 @@ impl Regular {
         data: &[u8],
         size: usize,
     ) -> Result<(), FileError> {
-        let meta_block = self.get_meta_block(bm)?;
-        let mut max_writable = round_up(meta_block.size, BLOCKSIZE as u32);
+        let size_before = self.get_meta_block(bm)?.size;
+        let mut max_writable = round_up(size_before, BLOCKSIZE as u32);
 
         let mut offset = self.current_offset();
         let mut written = 0;
@@ -132,22 +135,26 @@ impl Regular {
                 self.alloc_block(bm, Index::from_pointer(max_writable))?;
                 max_writable += BLOCKSIZE as u32;
             }
-            let mut block = self.get_current_block(bm)?;
+            // the pointer goes to the next block when this one is filled up
+            let id = self.get_current_block_id(bm)?;
+            let mut block = bm.read_block(id)?;
             while written < size && offset < BLOCKSIZE {
                 block[offset] = data[written];
                 offset += 1;
                 written += 1;
                 self.pointer += 1;
             }
-            self.write_current_block(bm, block)?;
+            bm.write_block(id, block)?;
             offset %= BLOCKSIZE;
         }
+        // alloc_block may have updated the meta block
+        let mut meta_block = self.get_meta_block(bm)?;
         meta_block.size = if meta_block.size > self.pointer {
             meta_block.size
         } else {
             self.pointer
         };
-        self.write_meta_block(bm, meta_block)?;
+        self.write_meta_block(bm, &meta_block)?;
         Ok(())
     }
 
@@ -176,7 +183,6 @@ impl Regular {
             }
             offset %= BLOCKSIZE;
         }
-        self.write_meta_block(bm, meta_block)?;
         Ok(())
     }
 
@@ -198,10 +204,38 @@ impl Regular {
         }
     }
 
-    pub fn rename(&mut self, bm: &mut BlockManager, name: [u8; 256]) -> Result<(), FileError> {
+    pub fn tell(&self) -> usize {
+        self.pointer as usize
+    }
+
+    pub fn size(&self, bm: &mut BlockManager) -> Result<usize, FileError> {
         let meta_block = self.get_meta_block(bm)?;
+        Ok(meta_block.size as usize)
+    }
+
+    // empty the file and free its blocks
+    pub fn truncate(&mut self, bm: &mut BlockManager) -> Result<(), FileError> {
+        let mut meta_block = self.get_meta_block(bm)?;
+        let tables = meta_block.data;
+        meta_block.data = [0; N_POINTER_PER_FILE];
+        meta_block.size = 0;
+        self.write_meta_block(bm, &meta_block)?;
+        self.pointer = 0;
+        // the file has forgotten the blocks, so an error here only leaks them
+        for &table in tables.iter().filter(|&&id| id != 0) {
+            let mut block = bm.read_block(Id(table))?;
+            for &id in as_table_mut(&mut block).iter().filter(|&&id| id != 0) {
+                bm.free_block(Id(id))?;
+            }
+            bm.free_block(Id(table))?;
+        }
+        Ok(())
+    }
+
+    pub fn rename(&mut self, bm: &mut BlockManager, name: [u8; 256]) -> Result<(), FileError> {
+        let mut meta_block = self.get_meta_block(bm)?;
         meta_block.name = name;
-        self.write_meta_block(bm, meta_block)?;
+        self.write_meta_block(bm, &meta_block)?;
         Ok(())
     }
 }
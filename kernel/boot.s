@@ -27,6 +27,11 @@ temporary_pgdir_ptr:
 tmp_reserved_page:
     .skip 4096
 
+# (buffer for reading a whole file from the disk. e.g. programs for execve)
+.global file_buffer_ptr
+file_buffer_ptr:
+    .skip 1048576
+
 .global kernel_frames_ptr
 kernel_frames_ptr:
     .skip 2097152
@@ -36,6 +41,7 @@ stack_stop:
     .skip 4096
 stack:
     .skip 4096000
+.global stack_end
 stack_end:
 
 .global interrupt_stack_stop
@@ -46,41 +52,18 @@ interrupt_stack:
 .global interrupt_stack_end
 interrupt_stack_end:
 
-# followings are not aligned data
+# (function symbols for backtraces. filled by embed_symbols.py after linking)
+.section .ksymtab, "a",@progbits
+.balign 4
+.global ksymtab_start
+ksymtab_start:
+    .skip 524288
+
 .option norvc
-.section .programs, "ax",@progbits
-.global nop_start
-nop_start:
-    .incbin "../misc/bin/nop"
-.global nop_end
-nop_end:
-.global loop_start
-loop_start:
-    .incbin "../misc/bin/loop"
-.global loop_end
-loop_end:
-.global sh_start
-sh_start:
-    .incbin "../misc/bin/sh"
-.global sh_end
-sh_end:
-.global syscaller_start
-syscaller_start:
-    .incbin "../misc/bin/syscaller"
-.global syscaller_end
-syscaller_end:
-.global hello_start
-hello_start:
-    .incbin "../misc/bin/hello"
-.global hello_end
-hello_end:
-.global tic_start
-tic_start:
-    .incbin "../misc/bin/tic"
-.global tic_end
-tic_end:
-.global catch_ball_start
-catch_ball_start:
-    .incbin "../misc/bin/catch_ball"
-.global catch_ball_end
-catch_ball_end:
+.section .disk, "aw",@progbits
+.balign 4096
+.global disk_start
+disk_start:
+    .incbin "../fs/disk.img"
+.global disk_end
+disk_end:
@@ -0,0 +1,92 @@
+import struct
+import sys
+
+# Copy the function symbols of the kernel ELF into its .ksymtab section,
+# so that the kernel can print symbolized backtraces.
+#
+# layout of .ksymtab (see src/backtrace.rs and elf::SymbolTable)
+#   magic "KSYM"
+#   section header of .symtab  (offsets are relative to this header)
+#   section header of .strtab
+#   symbols, sorted by their address
+#   names
+
+ELF = "bin/osmium"
+SECTION = b".ksymtab"
+MAGIC = b"KSYM"
+
+SHT_SYMTAB = 2
+SHT_STRTAB = 3
+STT_FUNC = 2
+SECTION_HEADER = "<10I"
+SYMBOL = "<3IBBH"
+
+
+def section_headers(elf):
+    shoff, = struct.unpack_from("<I", elf, 0x20)
+    shentsize, shnum, shstrndx = struct.unpack_from("<3H", elf, 0x2e)
+    headers = [struct.unpack_from(SECTION_HEADER, elf, shoff + i * shentsize)
+               for i in range(shnum)]
+    strtab = headers[shstrndx]
+    names = []
+    for h in headers:
+        start = strtab[4] + h[0]
+        names.append(elf[start:elf.index(b"\0", start)])
+    return list(zip(names, headers))
+
+
+def function_symbols(elf, sections):
+    symtab = [h for (_, h) in sections if h[1] == SHT_SYMTAB][0]
+    strtab = sections[symtab[6]][1]
+    symbols = {}
+    for off in range(symtab[4], symtab[4] + symtab[5], symtab[9]):
+        name, value, size, info, _, _ = struct.unpack_from(SYMBOL, elf, off)
+        if info & 0xf != STT_FUNC or value == 0:
+            continue
+        start = strtab[4] + name
+        symbols[value] = (size, elf[start:elf.index(b"\0", start)])
+    return sorted(symbols.items())
+
+
+def build_table(symbols):
+    header_size = struct.calcsize(SECTION_HEADER)
+    symtab_offset = header_size * 2
+    symtab_size = len(symbols) * struct.calcsize(SYMBOL)
+    strtab_offset = symtab_offset + symtab_size
+
+    syms = b""
+    names = b"\0"
+    for (value, (size, name)) in symbols:
+        syms += struct.pack(SYMBOL, len(names), value, size, STT_FUNC, 0, 0)
+        names += name + b"\0"
+
+    symtab = struct.pack(SECTION_HEADER, 0, SHT_SYMTAB, 0, 0, symtab_offset,
+                         symtab_size, 1, 0, 4, struct.calcsize(SYMBOL))
+    strtab = struct.pack(SECTION_HEADER, 0, SHT_STRTAB, 0, 0, strtab_offset,
+                         len(names), 0, 0, 1, 0)
+    return MAGIC + symtab + strtab + syms + names
+
+
+def main():
+    path = sys.argv[1] if len(sys.argv) > 1 else ELF
+    with open(path, "rb") as f:
+        elf = bytearray(f.read())
+
+    sections = section_headers(elf)
+    target = [h for (name, h) in sections if name == SECTION]
+    if not target:
+        print("{} has no {} section".format(path, SECTION.decode()))
+        sys.exit(1)
+    offset, size = target[0][4], target[0][5]
+
+    table = build_table(function_symbols(elf, sections))
+    if len(table) > size:
+        print("symbol table is too big: {} > {}".format(len(table), size))
+        sys.exit(1)
+    elf[offset:offset + size] = table + b"\0" * (size - len(table))
+
+    with open(path, "wb") as f:
+        f.write(elf)
+
+
+main()
@@ -0,0 +1,142 @@
+use core::mem;
+use memlayout;
+use osmium_syscall::args::{Arg, ArgsInfo, ARGS_INFO_ADDR, MAX_ARGS, MAX_ARGS_SIZE};
+use osmium_syscall::errors::SyscallError;
+use paging;
+use usermem;
+
+// the address of the i-th element of size `size` in the array at addr
+fn element_addr(addr: u32, i: usize, size: u32) -> Result<u32, SyscallError> {
+    (i as u32)
+        .checked_mul(size)
+        .and_then(|offset| addr.checked_add(offset))
+        .ok_or(SyscallError::BadAddress)
+}
+
+// arguments of execve, kept in the kernel while the user memory is replaced
+pub struct Args {
+    strings: [u8; MAX_ARGS_SIZE],
+    used: usize,
+    // (offset in strings, length)
+    argv: [(usize, usize); MAX_ARGS],
+    argc: usize,
+    envp: [(usize, usize); MAX_ARGS],
+    envc: usize,
+}
+
+impl Args {
+    pub fn new() -> Args {
+        Args {
+            strings: [0; MAX_ARGS_SIZE],
+            used: 0,
+            argv: [(0, 0); MAX_ARGS],
+            argc: 0,
+            envp: [(0, 0); MAX_ARGS],
+            envc: 0,
+        }
+    }
+
+    fn push_string(&mut self, map: &paging::Map, arg: Arg) -> Result<(usize, usize), SyscallError> {
+        let len = arg.len as usize;
+        if len > MAX_ARGS_SIZE - self.used {
+            return Err(SyscallError::InvalidArguments);
+        }
+        let offset = self.used;
+        usermem::copy_from_user(map, &mut self.strings[offset..offset + len], arg.ptr)?;
+        self.used += len;
+        Ok((offset, len))
+    }
+
+    // copy an array of Arg terminated by null. returns the number of entries
+    fn copy_array(
+        &mut self,
+        map: &paging::Map,
+        addr: u32,
+        is_env: bool,
+    ) -> Result<usize, SyscallError> {
+        if addr == 0 {
+            return Ok(0);
+        }
+        let size = mem::size_of::<Arg>() as u32;
+        for i in 0..(MAX_ARGS + 1) {
+            let mut arg = Arg::null();
+            usermem::read_from_user(map, element_addr(addr, i, size)?, &mut arg)?;
+            if arg.is_null() {
+                return Ok(i);
+            }
+            if i == MAX_ARGS {
+                break;
+            }
+            let entry = self.push_string(map, arg)?;
+            if is_env {
+                self.envp[i] = entry;
+            } else {
+                self.argv[i] = entry;
+            }
+        }
+        Err(SyscallError::InvalidArguments)
+    }
+
+    // map must be the current address space
+    pub fn copy_from_user(
+        &mut self,
+        map: &paging::Map,
+        argv: u32,
+        envp: u32,
+    ) -> Result<(), SyscallError> {
+        self.argc = self.copy_array(map, argv, false)?;
+        self.envc = self.copy_array(map, envp, true)?;
+        Ok(())
+    }
+
+    // write the arguments to the top of the user stack in the layout described in
+    // osmium_syscall::args, and return the initial stack pointer.
+    // map must be the current address space
+    pub fn push_to_user_stack(
+        &self,
+        map: &mut paging::Map,
+        allocator: &mut paging::Allocator,
+    ) -> Result<u32, SyscallError> {
+        debug_assert!(
+            ARGS_INFO_ADDR as usize + mem::size_of::<ArgsInfo>()
+                == (memlayout::USER_STACK_TOP + memlayout::USER_STACK_SIZE) as usize
+        );
+        let strings_addr = ARGS_INFO_ADDR - self.used as u32;
+        usermem::copy_to_user(map, strings_addr, &self.strings[..self.used], allocator)?;
+
+        let arg_size = mem::size_of::<Arg>() as u32;
+        let argv_addr = ((self.argc + self.envc) as u32)
+            .checked_mul(arg_size)
+            .and_then(|size| strings_addr.checked_sub(size))
+            .ok_or(SyscallError::BadAddress)?
+            & !0x3;
+        let envp_addr = element_addr(argv_addr, self.argc, arg_size)?;
+        let entries = self.argv[..self.argc]
+            .iter()
+            .chain(self.envp[..self.envc].iter());
+        for (i, &(offset, len)) in entries.enumerate() {
+            let arg = Arg {
+                ptr: element_addr(strings_addr, offset, 1)?,
+                len: len as u32,
+            };
+            let addr = element_addr(argv_addr, i, arg_size)?;
+            usermem::write_to_user(map, addr, &arg, allocator)?;
+        }
+
+        let info = ArgsInfo {
+            argc: self.argc as u32,
+            argv: argv_addr,
+            envc: self.envc as u32,
+            envp: envp_addr,
+        };
+        usermem::write_to_user(map, ARGS_INFO_ADDR, &info, allocator)?;
+        Ok(argv_addr & !0xf)
+    }
+}
+
+#[test]
+fn test_element_addr() {
+    assert_eq!(element_addr(0x1000, 3, 8).ok(), Some(0x1018));
+    assert!(element_addr(0xffff_fff0, 2, 8).is_err());
+    assert!(element_addr(0x1000, 1 << 30, 8).is_err());
+}
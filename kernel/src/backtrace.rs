@@ -0,0 +1,231 @@
+use core::fmt;
+use core::slice;
+use elf;
+use trap::TrapFrame;
+
+// Backtraces of the kernel, walked through frame pointers (-C force-frame-pointers).
+// Each frame looks like
+//   fp - 4: return address
+//   fp - 8: frame pointer of the caller
+
+extern "C" {
+    static ksymtab_start: u8;
+    static mut stack_stop: u8;
+    static stack_end: u8;
+    static mut interrupt_stack_stop: u8;
+    static interrupt_stack_end: u8;
+}
+
+// must be the same as the size of .ksymtab in boot.s
+const KSYMTAB_SIZE: usize = 524288;
+const KSYMTAB_MAGIC: &[u8] = b"KSYM";
+const MAX_FRAMES: usize = 32;
+
+// the function symbols embedded by embed_symbols.py
+fn symbol_table() -> Option<elf::SymbolTable<'static>> {
+    let bytes = unsafe { slice::from_raw_parts(&ksymtab_start as *const u8, KSYMTAB_SIZE) };
+    if &bytes[..KSYMTAB_MAGIC.len()] != KSYMTAB_MAGIC {
+        return None;
+    }
+    elf::SymbolTable::new(&bytes[KSYMTAB_MAGIC.len()..]).ok()
+}
+
+fn on_kernel_stack(fp: u32) -> bool {
+    let stacks = unsafe {
+        [
+            (
+                &stack_stop as *const u8 as u32,
+                &stack_end as *const u8 as u32,
+            ),
+            (
+                &interrupt_stack_stop as *const u8 as u32,
+                &interrupt_stack_end as *const u8 as u32,
+            ),
+        ]
+    };
+    fp % 4 == 0
+        && stacks
+            .iter()
+            .any(|&(stop, end)| stop + 8 <= fp && fp <= end)
+}
+
+fn print_frame(symbols: &Option<elf::SymbolTable>, depth: usize, pc: u32) {
+    match symbols.as_ref().and_then(|s| s.lookup(pc)) {
+        Some((name, offset)) => println!(
+            "  #{:<2} {:08x} {}+{:#x}",
+            depth,
+            pc,
+            Demangle(name),
+            offset
+        ),
+        None => println!("  #{:<2} {:08x} ???", depth, pc),
+    }
+}
+
+fn walk(symbols: &Option<elf::SymbolTable>, mut fp: u32, mut depth: usize) {
+    while depth < MAX_FRAMES && on_kernel_stack(fp) {
+        let (ra, caller_fp) = unsafe { (*((fp - 4) as *const u32), *((fp - 8) as *const u32)) };
+        if ra == 0 {
+            return;
+        }
+        // the call instruction. the kernel is built without compressed instructions
+        print_frame(symbols, depth, ra - 4);
+        // the stack grows downward
+        if caller_fp <= fp {
+            return;
+        }
+        fp = caller_fp;
+        depth += 1;
+    }
+}
+
+// backtrace of the caller
+pub fn print_backtrace() {
+    let fp: u32;
+    unsafe {
+        asm!("mv $0, s0" : "=r"(fp));
+    }
+    println!("backtrace:");
+    walk(&symbol_table(), fp, 0);
+}
+
+// backtrace of the kernel context interrupted by tf
+pub fn print_trap_backtrace(tf: &TrapFrame) {
+    let symbols = symbol_table();
+    println!("backtrace of the trapped context:");
+    print_frame(&symbols, 0, tf.pc);
+    walk(&symbols, tf.regs.int_regs[8], 1);
+}
+
+// rust symbol mangled in the legacy scheme. e.g.
+// _ZN6osmium4trap4trap17h0123456789abcdefE -> osmium::trap::trap
+struct Demangle<'a>(&'a str);
+
+// split "<len><ident>" from the head of s
+fn next_ident(s: &str) -> Option<(&str, &str)> {
+    let digits = s.bytes().take_while(|c| c.is_ascii_digit()).count();
+    let len: usize = s[..digits].parse().ok()?;
+    let rest = &s[digits..];
+    if len == 0 || rest.len() < len || !rest.is_char_boundary(len) {
+        return None;
+    }
+    Some((&rest[..len], &rest[len..]))
+}
+
+fn is_hash(ident: &str) -> bool {
+    ident.len() == 17 && ident.starts_with('h') && ident[1..].bytes().all(|c| c.is_ascii_hexdigit())
+}
+
+fn write_ident(f: &mut fmt::Formatter, ident: &str) -> fmt::Result {
+    let mut rest = if ident.starts_with("_$") {
+        &ident[1..]
+    } else {
+        ident
+    };
+    while !rest.is_empty() {
+        if rest.starts_with("..") {
+            f.write_str("::")?;
+            rest = &rest[2..];
+        } else if rest.starts_with('$') {
+            let end = match rest[1..].find('$') {
+                Some(i) => i + 2,
+                None => return f.write_str(rest),
+            };
+            let escaped = match &rest[1..end - 1] {
+                "SP" => "@",
+                "BP" => "*",
+                "RF" => "&",
+                "LT" => "<",
+                "GT" => ">",
+                "LP" => "(",
+                "RP" => ")",
+                "C" => ",",
+                "u20" => " ",
+                "u22" => "\"",
+                "u27" => "'",
+                "u5b" => "[",
+                "u5d" => "]",
+                "u7b" => "{",
+                "u7d" => "}",
+                "u7e" => "~",
+                _ => &rest[..end],
+            };
+            f.write_str(escaped)?;
+            rest = &rest[end..];
+        } else {
+            let len = rest.find(|c| c == '$' || c == '.').unwrap_or(rest.len());
+            let len = if len == 0 { 1 } else { len };
+            f.write_str(&rest[..len])?;
+            rest = &rest[len..];
+        }
+    }
+    Ok(())
+}
+
+impl<'a> fmt::Display for Demangle<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.0.starts_with("_ZN") || !self.0.ends_with('E') {
+            return f.write_str(self.0);
+        }
+        let path = &self.0[3..self.0.len() - 1];
+
+        // check the whole path first, so that nothing is written for broken symbols
+        let mut rest = path;
+        while !rest.is_empty() {
+            match next_ident(rest) {
+                Some((_, r)) => rest = r,
+                None => return f.write_str(self.0),
+            }
+        }
+
+        let mut rest = path;
+        let mut first = true;
+        while let Some((ident, r)) = next_ident(rest) {
+            rest = r;
+            if rest.is_empty() && is_hash(ident) {
+                break;
+            }
+            if !first {
+                f.write_str("::")?;
+            }
+            write_ident(f, ident)?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_demangle() {
+    use core::fmt::Write;
+    use core::str;
+    struct Buffer {
+        bytes: [u8; 128],
+        len: usize,
+    }
+    impl fmt::Write for Buffer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.bytes[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+            self.len += s.len();
+            Ok(())
+        }
+    }
+    let check = |mangled: &str, expected: &str| {
+        let mut buf = Buffer {
+            bytes: [0; 128],
+            len: 0,
+        };
+        write!(buf, "{}", Demangle(mangled)).unwrap();
+        assert_eq!(str::from_utf8(&buf.bytes[..buf.len]).unwrap(), expected);
+    };
+    check(
+        "_ZN6osmium4trap4trap17h0123456789abcdefE",
+        "osmium::trap::trap",
+    );
+    check(
+        "_ZN65_$LT$osmium..proc..ProcessError$u20$as$u20$core..fmt..Display$GT$3fmt17h0123456789abcdefE",
+        "<osmium::proc::ProcessError as core::fmt::Display>::fmt",
+    );
+    check("trap_entry", "trap_entry");
+    check("_ZN6osmium99trapE", "_ZN6osmium99trapE");
+}
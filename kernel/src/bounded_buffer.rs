@@ -1,3 +1,5 @@
+use osmium_syscall::ipc::MAX_QUEUE_CAPACITY;
+
 #[derive(Debug)]
 pub enum Error {
     Full,
@@ -6,42 +8,72 @@ pub enum Error {
 
 // instant implmentation of bounded buffer
 // this is not thread safe
-const SIZE: usize = 8;
+// one slot of the ring is always empty, so it holds at most SIZE - 1 elements
+const SIZE: usize = MAX_QUEUE_CAPACITY + 1;
 pub struct BoundedBuffer<T: Sized + Copy> {
     buffer: [T; SIZE],
+    // the length of the ring in use. capacity + 1
+    size: usize,
     next_in: usize,
     next_out: usize,
 }
 
-fn count_up(v: usize) -> usize {
-    let v = v + 1;
-    if v == SIZE {
-        0
-    } else {
-        v
-    }
-}
-
 impl<T: Sized + Copy> BoundedBuffer<T> {
     pub fn new(init: T) -> BoundedBuffer<T> {
+        BoundedBuffer::with_capacity(init, SIZE - 1)
+    }
+    pub fn with_capacity(init: T, capacity: usize) -> BoundedBuffer<T> {
+        assert!(0 < capacity && capacity < SIZE);
         BoundedBuffer {
             buffer: [init; SIZE],
+            size: capacity + 1,
             next_in: 0,
             next_out: 0,
         }
     }
+    fn count_up(&self, v: usize) -> usize {
+        let v = v + 1;
+        if v == self.size {
+            0
+        } else {
+            v
+        }
+    }
+    pub fn capacity(&self) -> usize {
+        self.size - 1
+    }
+    // fails with Full if it has more elements than `capacity`
+    pub fn set_capacity(&mut self, capacity: usize) -> Result<(), Error> {
+        assert!(0 < capacity && capacity < SIZE);
+        let len = self.len();
+        if len > capacity {
+            return Err(Error::Full);
+        }
+        // move the elements to the head of the buffer
+        let mut buffer = self.buffer;
+        let mut idx = self.next_out;
+        for x in buffer.iter_mut().take(len) {
+            *x = self.buffer[idx];
+            idx = self.count_up(idx);
+        }
+        self.buffer = buffer;
+        self.size = capacity + 1;
+        self.next_out = 0;
+        self.next_in = len;
+        Ok(())
+    }
     pub fn is_empty(&self) -> bool {
         self.next_in == self.next_out
     }
     pub fn is_full(&self) -> bool {
-        count_up(self.next_in) == self.next_out
+        self.count_up(self.next_in) == self.next_out
     }
     pub fn enqueue(&mut self, val: T) -> Result<(), Error> {
         if self.is_full() {
             return Err(Error::Full);
         }
         self.buffer[self.next_in] = val;
-        self.next_in = count_up(self.next_in);
+        self.next_in = self.count_up(self.next_in);
         Ok(())
     }
     pub fn dequeue(&mut self) -> Result<T, Error> {
@@ -49,14 +81,40 @@ impl<T: Sized + Copy> BoundedBuffer<T> {
             return Err(Error::Empty);
         }
         let idx = self.next_out;
-        self.next_out = count_up(idx);
+        self.next_out = self.count_up(idx);
         Ok(self.buffer[idx])
     }
+    // take the first element which satisfies f. the order of the others is kept
+    pub fn dequeue_first<F: Fn(&T) -> bool>(&mut self, f: F) -> Result<T, Error> {
+        let mut idx = self.next_out;
+        while idx != self.next_in {
+            if f(&self.buffer[idx]) {
+                let val = self.buffer[idx];
+                // shift the following elements
+                let mut next = self.count_up(idx);
+                while next != self.next_in {
+                    self.buffer[idx] = self.buffer[next];
+                    idx = next;
+                    next = self.count_up(next);
+                }
+                self.next_in = idx;
+                return Ok(val);
+            }
+            idx = self.count_up(idx);
+        }
+        Err(Error::Empty)
+    }
+    pub fn peek(&self) -> Result<T, Error> {
+        if self.is_empty() {
+            return Err(Error::Empty);
+        }
+        Ok(self.buffer[self.next_out])
+    }
     pub fn len(&self) -> usize {
         if self.next_out <= self.next_in {
             self.next_in - self.next_out
         } else {
-            self.next_in + SIZE - self.next_out
+            self.next_in + self.size - self.next_out
         }
     }
 }
@@ -76,3 +134,45 @@ fn test_bb() {
     assert_eq!(bb.dequeue().unwrap(), 2);
     assert_eq!(bb.len(), SIZE - 3);
 }
+
+#[test]
+fn test_bb_capacity() {
+    let bb = &mut BoundedBuffer::with_capacity(0, 3);
+    for i in 0..3 {
+        bb.enqueue(i).unwrap();
+    }
+    assert!(bb.is_full());
+    assert_eq!(bb.dequeue().unwrap(), 0);
+    // the ring wraps around
+    bb.enqueue(3).unwrap();
+    assert!(bb.set_capacity(2).is_err());
+    bb.set_capacity(5).unwrap();
+    assert_eq!(bb.capacity(), 5);
+    bb.enqueue(4).unwrap();
+    bb.enqueue(5).unwrap();
+    assert!(bb.is_full());
+    assert_eq!(bb.peek().unwrap(), 1);
+    for i in 1..6 {
+        assert_eq!(bb.dequeue().unwrap(), i);
+    }
+    assert!(bb.is_empty());
+}
+
+#[test]
+fn test_bb_dequeue_first() {
+    let bb = &mut BoundedBuffer::with_capacity(0, 4);
+    bb.enqueue(1).unwrap();
+    bb.dequeue().unwrap();
+    // 2, 3, 4, 5 wrapping around the ring
+    for i in 2..6 {
+        bb.enqueue(i).unwrap();
+    }
+    assert!(bb.dequeue_first(|&x| x == 6).is_err());
+    assert_eq!(bb.dequeue_first(|&x| x % 2 == 1).unwrap(), 3);
+    assert_eq!(bb.len(), 3);
+    bb.enqueue(6).unwrap();
+    assert!(bb.is_full());
+    for &i in [2, 4, 5, 6].iter() {
+        assert_eq!(bb.dequeue().unwrap(), i);
+    }
+}
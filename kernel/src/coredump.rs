@@ -0,0 +1,246 @@
+/* dumps the image of a crashed process to /core.<pid> as an ELF core file */
+use core::cmp;
+use core::mem;
+use core::slice;
+use elf;
+use files;
+use osmium_fs::filesystem::FileSystem;
+use osmium_fs::{File, FileError};
+use paging;
+use proc;
+use trap::TrapFrame;
+use utils;
+
+const NT_PRSTATUS: u32 = 1;
+const MAX_CORE_PATH_LENGTH: usize = 16;
+// the disk is a copy in the memory, so a core file is lost when the machine stops. with this
+// on, the core is also printed in hex to the console, where scripts/extract_core.py takes it
+pub const STREAM_TO_UART: bool = false;
+// the number of bytes in a line of the hex dump
+const HEX_LINE_LENGTH: usize = 32;
+
+// struct elf_prstatus of riscv32 linux, which gdb understands
+#[repr(C)]
+struct Prstatus {
+    // siginfo
+    signo: u32,
+    code: u32,
+    errno: u32,
+    cursig: u16,
+    pad: u16,
+    sigpend: u32,
+    sighold: u32,
+    pid: u32,
+    ppid: u32,
+    pgrp: u32,
+    sid: u32,
+    times: [u32; 8],
+    // pc, x1, ..., x31
+    regs: [u32; 32],
+    fpvalid: u32,
+}
+
+#[repr(C)]
+struct Note {
+    namesz: u32,
+    descsz: u32,
+    ntype: u32,
+    // "CORE\0" padded to 4 bytes
+    name: [u8; 8],
+    prstatus: Prstatus,
+}
+
+impl Note {
+    fn prstatus(p: &proc::Process, tf: &TrapFrame, signal: u32) -> Note {
+        let mut regs = tf.regs.int_regs;
+        regs[0] = tf.pc;
+        // the slot of sp holds scause (see trap_entry)
+        regs[2] = tf.sp;
+        Note {
+            namesz: 5,
+            descsz: mem::size_of::<Prstatus>() as u32,
+            ntype: NT_PRSTATUS,
+            name: *b"CORE\0\0\0\0",
+            prstatus: Prstatus {
+                signo: signal,
+                code: 0,
+                errno: 0,
+                cursig: signal as u16,
+                pad: 0,
+                sigpend: 0,
+                sighold: 0,
+                pid: p.id.to_u32(),
+                ppid: p.parent_id.to_u32(),
+                pgrp: 0,
+                sid: 0,
+                times: [0; 8],
+                regs,
+                fpvalid: 0,
+            },
+        }
+    }
+}
+
+fn core_path(id: proc::Id, buf: &mut [u8; MAX_CORE_PATH_LENGTH]) -> &[u8] {
+    let prefix = b"/core.";
+    buf[..prefix.len()].copy_from_slice(prefix);
+    let mut digits = [0u8; 10];
+    let mut n = 0;
+    let mut x = id.to_u32();
+    loop {
+        digits[n] = b'0' + (x % 10) as u8;
+        n += 1;
+        x /= 10;
+        if x == 0 {
+            break;
+        }
+    }
+    for i in 0..n {
+        buf[prefix.len() + i] = digits[n - 1 - i];
+    }
+    &buf[..prefix.len() + n]
+}
+
+struct CoreFile<'a, 'b: 'a> {
+    fs: &'a mut FileSystem<'b>,
+    file: File,
+    // bytes in the current line of the hex dump
+    column: usize,
+}
+
+impl<'a, 'b> CoreFile<'a, 'b> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), FileError> {
+        self.fs.write(&mut self.file, bytes, bytes.len())?;
+        if STREAM_TO_UART {
+            for byte in bytes {
+                print!("{:02x}", byte);
+                self.column += 1;
+                if self.column == HEX_LINE_LENGTH {
+                    println!();
+                    self.column = 0;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_value<T>(&mut self, value: &T) -> Result<(), FileError> {
+        let bytes =
+            unsafe { slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) };
+        self.write(bytes)
+    }
+}
+
+// call f on every run of contiguous user pages with the same flag
+fn for_each_segment<F>(map: &paging::Map, mut f: F) -> Result<(), FileError>
+where
+    F: FnMut(u32, u32, paging::Flag) -> Result<(), FileError>,
+{
+    let mut segment: Option<(u32, u32, paging::Flag)> = None;
+    map.for_each_user_page(|page, flag| {
+        let addr = page.base_addr().to_u32();
+        match segment {
+            Some((start, size, prev)) if start + size == addr && prev == flag => {
+                segment = Some((start, size + paging::PGSIZE as u32, flag));
+                return Ok(());
+            }
+            Some((start, size, prev)) => f(start, size, prev)?,
+            None => (),
+        }
+        segment = Some((addr, paging::PGSIZE as u32, flag));
+        Ok(())
+    })?;
+    match segment {
+        Some((start, size, flag)) => f(start, size, flag),
+        None => Ok(()),
+    }
+}
+
+// the file consists of
+//   ELF header
+//   program headers (PT_NOTE, PT_LOAD...)
+//   NT_PRSTATUS note
+//   memory of the segments, from a page boundary
+pub fn dump(
+    fs: &mut FileSystem,
+    p: &proc::Process,
+    tf: &TrapFrame,
+    signal: u32,
+) -> Result<(), FileError> {
+    let mut path = [0u8; MAX_CORE_PATH_LENGTH];
+    let file = files::create_or_truncate(fs, core_path(p.id, &mut path))?;
+    let mut core = CoreFile {
+        fs,
+        file,
+        column: 0,
+    };
+    if STREAM_TO_UART {
+        println!("-----BEGIN CORE {}-----", p.id.to_u32());
+    }
+    write_core(&mut core, p, tf, signal)?;
+    if STREAM_TO_UART {
+        if core.column != 0 {
+            println!();
+        }
+        println!("-----END CORE {}-----", p.id.to_u32());
+    }
+    Ok(())
+}
+
+fn write_core(
+    core: &mut CoreFile,
+    p: &proc::Process,
+    tf: &TrapFrame,
+    signal: u32,
+) -> Result<(), FileError> {
+    let mut n_segments = 0;
+    for_each_segment(&p.mapper, |_, _, _| {
+        n_segments += 1;
+        Ok(())
+    })?;
+    let phnum = n_segments + 1;
+    let note_offset =
+        mem::size_of::<elf::ElfHeader>() + phnum * mem::size_of::<elf::ProgramHeader>();
+    let note_end = note_offset + mem::size_of::<Note>();
+    let data_offset = utils::round_up(note_end as u64, paging::PGSIZE as u64) as u32;
+
+    core.write_value(&elf::ElfHeader::core(phnum as u16))?;
+    core.write_value(&elf::ProgramHeader::note(
+        note_offset as u32,
+        mem::size_of::<Note>() as u32,
+    ))?;
+    let mut offset = data_offset;
+    for_each_segment(&p.mapper, |va, size, flag| {
+        core.write_value(&elf::ProgramHeader::load(offset, va, size, flag))?;
+        offset += size;
+        Ok(())
+    })?;
+    core.write_value(&Note::prstatus(p, tf, signal))?;
+
+    let zeros = [0u8; 64];
+    let mut pad = data_offset as usize - note_end;
+    while pad > 0 {
+        let size = cmp::min(pad, zeros.len());
+        core.write(&zeros[..size])?;
+        pad -= size;
+    }
+    // segments are in the order of their pages
+    p.mapper.for_each_user_page(|page, _| {
+        let data = unsafe { slice::from_raw_parts(page.base_addr().as_ptr(), paging::PGSIZE) };
+        core.write(data)
+    })
+}
+
+#[test]
+fn test_core_file_layout() {
+    // gdb recognizes NT_PRSTATUS of riscv32 by its size
+    assert_eq!(mem::size_of::<Prstatus>(), 204);
+    assert_eq!(mem::size_of::<Note>() % 4, 0);
+
+    let mut buf = [0u8; MAX_CORE_PATH_LENGTH];
+    assert_eq!(core_path(proc::Id(0), &mut buf), b"/core.0");
+    assert_eq!(
+        core_path(proc::Id(4294967295), &mut buf),
+        b"/core.4294967295"
+    );
+}
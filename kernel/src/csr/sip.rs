@@ -3,6 +3,7 @@ use utils;
 
 pub struct SIP {
     pub timer: bool,
+    pub software: bool,
 }
 
 impl CSRRead for SIP {
@@ -17,6 +18,7 @@ impl CSRRead for SIP {
     fn from_u32(x: u32) -> SIP {
         SIP {
             timer: utils::bit_range(x, 5, 6) == 1,
+            software: utils::bit_range(x, 1, 2) == 1,
         }
     }
 }
@@ -56,13 +58,27 @@ impl CSRWrite for SIP {
     }
     fn to_u32(&self) -> u32 {
         let timer = if self.timer { 1 << 5 } else { 0 };
-        timer
+        let software = if self.software { 1 << 1 } else { 0 };
+        timer | software
     }
 }
 
 impl SIP {
     pub fn timer_off() {
-        let v = SIP { timer: true }.to_u32();
+        let v = SIP {
+            timer: true,
+            software: false,
+        }
+        .to_u32();
+        SIP::bit_clear(v);
+    }
+
+    pub fn software_off() {
+        let v = SIP {
+            timer: false,
+            software: true,
+        }
+        .to_u32();
         SIP::bit_clear(v);
     }
 }
@@ -1,6 +1,8 @@
 use csr::{CSRRead, CSRWrite};
 use utils;
 
+const SPP: u32 = 1 << 8;
+
 pub struct SSTATUS {
     pub spie: bool,
     pub sie: bool,
@@ -72,4 +74,18 @@ impl SSTATUS {
         .to_u32();
         SSTATUS::bit_set(v);
     }
+
+    pub fn sie_on() {
+        let v = SSTATUS {
+            spie: false,
+            sie: true,
+        }
+        .to_u32();
+        SSTATUS::bit_set(v);
+    }
+
+    // sret goes back to user mode. SPP is set when a trap is taken in supervisor mode
+    pub fn spp_user() {
+        SSTATUS::bit_clear(SPP);
+    }
 }
@@ -9,8 +9,8 @@ const CLOCK: u64 = 240 * 1000 * 1000;
 #[derive(Copy, Clone)]
 pub struct MicroSeccond(pub u64);
 
-fn ms2clk(x: MicroSeccond) -> u64 {
-    CLOCK * x.0 / 1000 * 1000
+pub fn ms2clk(x: MicroSeccond) -> u64 {
+    CLOCK / (1000 * 1000) * x.0
 }
 
 impl MicroSeccond {
@@ -25,6 +25,16 @@ pub fn set_interval(ns: MicroSeccond) {
     write_mtime_comp(current + clk);
 }
 
+// the current time in clocks
+pub fn now() -> u64 {
+    read_mtime()
+}
+
+// raise the timer interrupt when mtime reaches `clk`
+pub fn set_deadline(clk: u64) {
+    write_mtime_comp(clk);
+}
+
 #[allow(dead_code)]
 fn read_mtime_comp() -> u64 {
     unsafe { ({ *MTIME_COMP_HI } as u64) << 32 | ({ *MTIME_COMP_LO } as u64) }
@@ -1,42 +1,300 @@
-use core::slice;
+use core::fmt;
+use core::mem;
+use core::ptr;
+use core::str;
+use memlayout;
 use paging;
 
 const ELF_MAGIC: u32 = 0x464c457f;
+const ELF_CLASS_32: u8 = 1;
+const ELF_DATA_LSB: u8 = 1;
+const ELF_VERSION_CURRENT: u8 = 1;
+const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
+const ET_CORE: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_NOTE: u32 = 4;
+const EM_RISCV: u16 = 243;
+
+const DT_NULL: i32 = 0;
+const DT_SYMTAB: i32 = 6;
+const DT_RELA: i32 = 7;
+const DT_RELASZ: i32 = 8;
+const DT_RELAENT: i32 = 9;
+const DT_SYMENT: i32 = 11;
+const DT_REL: i32 = 17;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const STT_FUNC: u8 = 2;
+
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xfff1;
+
+const R_RISCV_NONE: u32 = 0;
+const R_RISCV_32: u32 = 1;
+const R_RISCV_RELATIVE: u32 = 3;
+const R_RISCV_JUMP_SLOT: u32 = 5;
 
 pub struct Elf<'a> {
     bytes: &'a [u8],
-    pub elf: &'a ElfHeader,
+    pub elf: ElfHeader,
+    // added to every virtual address in the file
+    base: u32,
 }
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug)]
 pub enum ElfError {
     InvalidMagic,
+    TooShort,
+    UnsupportedClass,
+    UnsupportedEndian,
+    UnsupportedVersion,
+    UnsupportedMachine,
+    UnsupportedType,
+    InvalidHeaderSize,
+    InvalidProgramHeaders,
+    InvalidSegment,
+    InvalidDynamicSection,
+    InvalidRelocation,
+    UnsupportedRelocation,
+    UndefinedSymbol,
+    InvalidSymbolTable,
+}
+
+impl ElfError {
+    fn to_str(&self) -> &'static str {
+        match self {
+            ElfError::InvalidMagic => "invalid magic",
+            ElfError::TooShort => "file is shorter than the ELF header",
+            ElfError::UnsupportedClass => "not a 32-bit ELF",
+            ElfError::UnsupportedEndian => "not a little endian ELF",
+            ElfError::UnsupportedVersion => "unsupported ELF version",
+            ElfError::UnsupportedMachine => "not a RISC-V executable",
+            ElfError::UnsupportedType => "not an executable file",
+            ElfError::InvalidHeaderSize => "invalid header size",
+            ElfError::InvalidProgramHeaders => "program headers are out of the file",
+            ElfError::InvalidSegment => "segment is out of the file or the user memory",
+            ElfError::InvalidDynamicSection => "invalid dynamic section",
+            ElfError::InvalidRelocation => "relocation is out of the segments",
+            ElfError::UnsupportedRelocation => "unsupported relocation type",
+            ElfError::UndefinedSymbol => "relocation refers to an undefined symbol",
+            ElfError::InvalidSymbolTable => "invalid symbol table",
+        }
+    }
+
+    // the file itself is valid ELF, but cannot be run on this kernel
+    pub fn is_unsupported(&self) -> bool {
+        match self {
+            ElfError::UnsupportedClass
+            | ElfError::UnsupportedEndian
+            | ElfError::UnsupportedVersion
+            | ElfError::UnsupportedMachine
+            | ElfError::UnsupportedType
+            | ElfError::UnsupportedRelocation => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ElfError({})", self.to_str())
+    }
+}
+
+// check that [va, va + size) is in the range where executables are loaded
+fn in_user_image(va: u32, size: u32) -> bool {
+    match va.checked_add(size) {
+        Some(end) => memlayout::USER_IMAGE_START <= va && end <= memlayout::USER_IMAGE_END,
+        None => false,
+    }
+}
+
+// check that [offset, offset + size) is in a file of length len
+fn in_file(offset: u32, size: u32, len: usize) -> bool {
+    match offset.checked_add(size) {
+        Some(end) => end as usize <= len,
+        None => false,
+    }
+}
+
+fn read<T: Copy>(bytes: &[u8], offset: usize) -> Option<T> {
+    match offset.checked_add(mem::size_of::<T>()) {
+        Some(end) if end <= bytes.len() => {
+            let ptr = bytes[offset..].as_ptr() as *const T;
+            Some(unsafe { ptr::read_unaligned(ptr) })
+        }
+        _ => None,
+    }
 }
 
 impl<'a> Elf<'a> {
     pub fn new(bytes: *const [u8]) -> Result<Elf<'a>, ElfError> {
         let bytes = unsafe { &*bytes };
-        for i in 0..100 {
-            dprint!("{} ", bytes[i]);
-            if i % 10 == 9 {
-                dprintln!();
-            }
-        }
-        let elf = unsafe {
-            let data: *const ElfHeader = bytes.as_ptr() as *const ElfHeader;
-            &*(data)
+        // the buffer may not be aligned for the header
+        let elf: ElfHeader = match read(bytes, 0) {
+            Some(elf) => elf,
+            None => return Err(ElfError::TooShort),
         };
         dprintln!("{:?}", elf);
         if elf.magic != ELF_MAGIC {
             return Err(ElfError::InvalidMagic);
         }
-        Ok(Elf { bytes, elf })
+        if elf.elf[0] != ELF_CLASS_32 {
+            return Err(ElfError::UnsupportedClass);
+        }
+        if elf.elf[1] != ELF_DATA_LSB {
+            return Err(ElfError::UnsupportedEndian);
+        }
+        if elf.elf[2] != ELF_VERSION_CURRENT || elf.version != ELF_VERSION_CURRENT as u32 {
+            return Err(ElfError::UnsupportedVersion);
+        }
+        if elf.machine != EM_RISCV {
+            return Err(ElfError::UnsupportedMachine);
+        }
+        let base = match elf.etype {
+            ET_EXEC => 0,
+            ET_DYN => memlayout::USER_PIE_BASE,
+            _ => return Err(ElfError::UnsupportedType),
+        };
+        if elf.size as usize != mem::size_of::<ElfHeader>()
+            || (elf.phnum != 0 && elf.phentsize as usize != mem::size_of::<ProgramHeader>())
+        {
+            return Err(ElfError::InvalidHeaderSize);
+        }
+        let ph_size = elf.phnum as u32 * elf.phentsize as u32;
+        if !in_file(elf.phoff, ph_size, bytes.len()) {
+            return Err(ElfError::InvalidProgramHeaders);
+        }
+
+        let e = Elf { bytes, elf, base };
+        for i in 0..(elf.phnum as usize) {
+            let ph = e.program_header(i);
+            let va = match ph.va.checked_add(base) {
+                Some(va) => va,
+                None => return Err(ElfError::InvalidSegment),
+            };
+            // segments mapped into the kernel, the page tables or the stack would be
+            // overwritten by the loader
+            if !in_file(ph.offset, ph.filesz, bytes.len())
+                || ph.filesz > ph.memsz
+                || va.checked_add(ph.memsz).is_none()
+                || (ph.ptype == PT_LOAD && ph.memsz != 0 && !in_user_image(va, ph.memsz))
+            {
+                return Err(ElfError::InvalidSegment);
+            }
+        }
+        Ok(e)
     }
-    pub fn programs(&'a self) -> Programs<'a> {
-        Programs {
-            data: self.bytes,
-            elf: self.elf,
+
+    pub fn entry(&self) -> u32 {
+        self.elf.entry.wrapping_add(self.base)
+    }
+
+    fn read<T: Copy>(&self, offset: usize) -> Option<T> {
+        read(self.bytes, offset)
+    }
+
+    // find a loadable segment which contains [va, va + size). va is not relocated.
+    // in_file requires the range to be backed by the file
+    fn find_segment(&self, va: u32, size: u32, in_file: bool) -> Option<ProgramHeader> {
+        let end = va.checked_add(size)?;
+        for i in 0..(self.elf.phnum as usize) {
+            let ph = self.program_header(i);
+            let len = if in_file { ph.filesz } else { ph.memsz };
+            if ph.ptype == PT_LOAD && ph.va <= va && end - ph.va <= len {
+                return Some(ph);
+            }
+        }
+        None
+    }
+
+    // file offset of the data at va
+    fn va_to_offset(&self, va: u32, size: u32) -> Option<usize> {
+        let ph = self.find_segment(va, size, true)?;
+        Some((ph.offset + (va - ph.va)) as usize)
+    }
+
+    // relocations to apply after loading. empty for ET_EXEC
+    pub fn relocations(&'a self) -> Result<Relocations<'a>, ElfError> {
+        let mut relocs = Relocations {
+            elf: self,
+            rela: 0,
+            count: 0,
+            entsize: mem::size_of::<Rela>(),
+            symtab: 0,
+            syment: mem::size_of::<Symbol>() as u32,
             i: 0,
+        };
+        if self.elf.etype != ET_DYN {
+            return Ok(relocs);
+        }
+        let dynamic = (0..(self.elf.phnum as usize))
+            .map(|i| self.program_header(i))
+            .find(|ph| ph.ptype == PT_DYNAMIC);
+        let dynamic = match dynamic {
+            Some(ph) => ph,
+            None => return Ok(relocs),
+        };
+
+        let mut rela = None;
+        let mut rela_size = 0;
+        let entry_size = mem::size_of::<Dynamic>();
+        for i in 0..(dynamic.filesz as usize / entry_size) {
+            let d: Dynamic = self
+                .read(dynamic.offset as usize + i * entry_size)
+                .ok_or(ElfError::InvalidDynamicSection)?;
+            match d.tag {
+                DT_NULL => break,
+                DT_RELA => rela = Some(d.val),
+                DT_RELASZ => rela_size = d.val,
+                DT_RELAENT => relocs.entsize = d.val as usize,
+                DT_SYMTAB => relocs.symtab = d.val,
+                DT_SYMENT => relocs.syment = d.val,
+                DT_REL => return Err(ElfError::UnsupportedRelocation),
+                _ => (),
+            }
+        }
+        let rela = match rela {
+            Some(rela) => rela,
+            None => return Ok(relocs),
+        };
+        if relocs.entsize < mem::size_of::<Rela>() {
+            return Err(ElfError::InvalidDynamicSection);
+        }
+        relocs.rela = self
+            .va_to_offset(rela, rela_size)
+            .ok_or(ElfError::InvalidDynamicSection)?;
+        relocs.count = rela_size as usize / relocs.entsize;
+        Ok(relocs)
+    }
+
+    // i must be less than phnum
+    fn program_header(&self, i: usize) -> ProgramHeader {
+        let offset = self.elf.phoff as usize + i * self.elf.phentsize as usize;
+        let ptr = self.bytes[offset..].as_ptr() as *const ProgramHeader;
+        unsafe { ptr::read_unaligned(ptr) }
+    }
+
+    pub fn programs(&'a self) -> Programs<'a> {
+        Programs { elf: self, i: 0 }
+    }
+
+    fn symbol_value(&self, symtab: u32, syment: u32, index: u32) -> Result<u32, ElfError> {
+        let va = index
+            .checked_mul(syment)
+            .and_then(|offset| offset.checked_add(symtab))
+            .ok_or(ElfError::InvalidRelocation)?;
+        let offset = self
+            .va_to_offset(va, mem::size_of::<Symbol>() as u32)
+            .ok_or(ElfError::InvalidRelocation)?;
+        let sym: Symbol = self.read(offset).ok_or(ElfError::InvalidRelocation)?;
+        match sym.shndx {
+            SHN_UNDEF => Err(ElfError::UndefinedSymbol),
+            SHN_ABS => Ok(sym.value),
+            _ => Ok(sym.value.wrapping_add(self.base)),
         }
     }
 }
@@ -50,9 +308,20 @@ pub struct Program<'a> {
     pub file_size: usize,
 }
 
+impl<'a> Program<'a> {
+    pub fn pages(&self) -> paging::PageRange {
+        paging::Page::range(self.virt_addr, self.mem_size as u32)
+    }
+
+    pub fn is_on_page(&self, page: paging::Page) -> bool {
+        let addr = page.base_addr().to_u32();
+        self.virt_addr.page_start_addr() <= addr
+            && addr < self.virt_addr.to_u32() + self.mem_size as u32
+    }
+}
+
 pub struct Programs<'a> {
-    pub data: &'a [u8],
-    pub elf: &'a ElfHeader,
+    pub elf: &'a Elf<'a>,
     pub i: usize,
 }
 
@@ -71,43 +340,110 @@ fn parse_elf_flag(flags: u32) -> paging::Flag {
     flag
 }
 
+fn to_elf_flags(flag: paging::Flag) -> u32 {
+    let mut flags = 0;
+    if flag.contains(paging::Flag::EXEC) {
+        flags |= 1;
+    }
+    // CoW pages are writable for the process
+    if flag.intersects(paging::Flag::WRITE | paging::Flag::COW) {
+        flags |= 1 << 1;
+    }
+    if flag.contains(paging::Flag::READ) {
+        flags |= 1 << 2;
+    }
+    flags
+}
+
 impl<'a> Iterator for Programs<'a> {
     type Item = Program<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.i < (self.elf.phnum as usize) {
-            dprintln!(
-                "file place at {}",
-                (self.elf.phoff as usize + self.i * self.elf.phentsize as usize)
-            );
-            let item = unsafe {
-                &*((self.data.as_ptr() as usize
-                    + self.elf.phoff as usize
-                    + self.i * self.elf.phentsize as usize)
-                    as *const ProgramHeader)
-            };
+        while self.i < (self.elf.elf.phnum as usize) {
+            // ranges are checked in Elf::new
+            let item = self.elf.program_header(self.i);
             self.i += 1;
-            let data = unsafe {
-                &*(slice::from_raw_parts(
-                    (self.data.as_ptr() as usize + item.offset as usize) as *const u8,
-                    item.filesz as usize,
-                ))
-            };
-            Some(Program {
-                virt_addr: paging::VirtAddr::new(item.va),
+            if item.ptype != PT_LOAD || item.memsz == 0 {
+                continue;
+            }
+            let start = item.offset as usize;
+            let data = &self.elf.bytes[start..start + item.filesz as usize];
+            return Some(Program {
+                virt_addr: paging::VirtAddr::new(item.va + self.elf.base),
                 phys_addr: paging::PhysAddr::new(item.pa as u64),
                 mem_size: item.memsz as usize,
                 file_size: item.filesz as usize,
                 flag: parse_elf_flag(item.flags),
                 data,
-            })
-        } else {
-            None
+            });
         }
+        None
     }
 }
 
-#[derive(Debug)]
+// a word in the loaded image to be overwritten with value
+pub struct Relocation {
+    pub addr: paging::VirtAddr,
+    pub value: u32,
+}
+
+pub struct Relocations<'a> {
+    elf: &'a Elf<'a>,
+    // file offset of the relocation table
+    rela: usize,
+    count: usize,
+    entsize: usize,
+    symtab: u32,
+    syment: u32,
+    i: usize,
+}
+
+impl<'a> Relocations<'a> {
+    fn relocate(&self, rela: Rela) -> Result<Option<Relocation>, ElfError> {
+        let elf = self.elf;
+        let value = match rela.info & 0xff {
+            R_RISCV_NONE => return Ok(None),
+            R_RISCV_RELATIVE => elf.base.wrapping_add(rela.addend as u32),
+            R_RISCV_32 | R_RISCV_JUMP_SLOT => elf
+                .symbol_value(self.symtab, self.syment, rela.info >> 8)?
+                .wrapping_add(rela.addend as u32),
+            _ => return Err(ElfError::UnsupportedRelocation),
+        };
+        if elf
+            .find_segment(rela.offset, mem::size_of::<u32>() as u32, false)
+            .is_none()
+        {
+            return Err(ElfError::InvalidRelocation);
+        }
+        Ok(Some(Relocation {
+            addr: paging::VirtAddr::new(rela.offset + elf.base),
+            value,
+        }))
+    }
+}
+
+impl<'a> Iterator for Relocations<'a> {
+    type Item = Result<Relocation, ElfError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.i < self.count {
+            let offset = self.rela + self.i * self.entsize;
+            self.i += 1;
+            let rela: Rela = match self.elf.read(offset) {
+                Some(rela) => rela,
+                None => return Some(Err(ElfError::InvalidDynamicSection)),
+            };
+            match self.relocate(rela) {
+                Ok(Some(reloc)) => return Some(Ok(reloc)),
+                Ok(None) => (),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct ElfHeader {
     pub magic: u32,
@@ -127,6 +463,7 @@ pub struct ElfHeader {
     pub shstrndx: u16,
 }
 
+#[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct ProgramHeader {
     pub ptype: u32,
@@ -139,7 +476,99 @@ pub struct ProgramHeader {
     pub align: u32,
 }
 
+impl ElfHeader {
+    // header of a core file, followed by phnum program headers
+    pub fn core(phnum: u16) -> ElfHeader {
+        ElfHeader {
+            magic: ELF_MAGIC,
+            elf: [
+                ELF_CLASS_32,
+                ELF_DATA_LSB,
+                ELF_VERSION_CURRENT,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            etype: ET_CORE,
+            machine: EM_RISCV,
+            version: ELF_VERSION_CURRENT as u32,
+            entry: 0,
+            phoff: mem::size_of::<ElfHeader>() as u32,
+            shoff: 0,
+            flags: 0,
+            size: mem::size_of::<ElfHeader>() as u16,
+            phentsize: mem::size_of::<ProgramHeader>() as u16,
+            phnum,
+            shentsize: 0,
+            shnum: 0,
+            shstrndx: 0,
+        }
+    }
+}
+
+impl ProgramHeader {
+    pub fn note(offset: u32, size: u32) -> ProgramHeader {
+        ProgramHeader {
+            ptype: PT_NOTE,
+            offset,
+            va: 0,
+            pa: 0,
+            filesz: size,
+            memsz: 0,
+            flags: 0,
+            align: 4,
+        }
+    }
+
+    // size bytes of memory at va, dumped at offset in the file
+    pub fn load(offset: u32, va: u32, size: u32, flag: paging::Flag) -> ProgramHeader {
+        ProgramHeader {
+            ptype: PT_LOAD,
+            offset,
+            va,
+            pa: 0,
+            filesz: size,
+            memsz: size,
+            flags: to_elf_flags(flag),
+            align: paging::PGSIZE as u32,
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Dynamic {
+    tag: i32,
+    val: u32,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Rela {
+    offset: u32,
+    info: u32,
+    addend: i32,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+struct Symbol {
+    name: u32,
+    value: u32,
+    size: u32,
+    info: u8,
+    other: u8,
+    shndx: u16,
+}
+
 #[allow(dead_code)]
+#[derive(Copy, Clone)]
 #[repr(C)]
 struct SectionHeader {
     name: u32,
@@ -153,3 +582,268 @@ struct SectionHeader {
     addralign: u32,
     entsize: u32,
 }
+
+// function symbols made from a .symtab and its .strtab.
+// `bytes` starts with the two section headers, whose offsets are relative to `bytes`,
+// and the symbols must be sorted by their address (see embed_symbols.py)
+pub struct SymbolTable<'a> {
+    bytes: &'a [u8],
+    symtab: SectionHeader,
+    strtab: SectionHeader,
+}
+
+impl<'a> SymbolTable<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<SymbolTable<'a>, ElfError> {
+        let symtab: SectionHeader = read(bytes, 0).ok_or(ElfError::InvalidSymbolTable)?;
+        let strtab: SectionHeader =
+            read(bytes, mem::size_of::<SectionHeader>()).ok_or(ElfError::InvalidSymbolTable)?;
+        if symtab.shtype != SHT_SYMTAB
+            || strtab.shtype != SHT_STRTAB
+            || symtab.entsize as usize != mem::size_of::<Symbol>()
+            || !in_file(symtab.offset, symtab.size, bytes.len())
+            || !in_file(strtab.offset, strtab.size, bytes.len())
+        {
+            return Err(ElfError::InvalidSymbolTable);
+        }
+        Ok(SymbolTable {
+            bytes,
+            symtab,
+            strtab,
+        })
+    }
+
+    fn len(&self) -> usize {
+        (self.symtab.size / self.symtab.entsize) as usize
+    }
+
+    fn symbol(&self, i: usize) -> Symbol {
+        read(
+            self.bytes,
+            self.symtab.offset as usize + i * mem::size_of::<Symbol>(),
+        )
+        .unwrap()
+    }
+
+    fn name(&self, offset: u32) -> Option<&'a str> {
+        if offset >= self.strtab.size {
+            return None;
+        }
+        let start = (self.strtab.offset + offset) as usize;
+        let end = (self.strtab.offset + self.strtab.size) as usize;
+        let names = &self.bytes[start..end];
+        let len = names.iter().position(|&c| c == 0)?;
+        str::from_utf8(&names[..len]).ok()
+    }
+
+    // the function containing addr, and the offset of addr from its start
+    pub fn lookup(&self, addr: u32) -> Option<(&'a str, u32)> {
+        // find the last symbol which starts at or before addr
+        let mut lo = 0;
+        let mut hi = self.len();
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.symbol(mid).value <= addr {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        if lo == 0 {
+            return None;
+        }
+        let sym = self.symbol(lo - 1);
+        let offset = addr - sym.value;
+        // symbols without size (e.g. written in assembly) extend to the next one
+        if sym.info & 0xf != STT_FUNC || (sym.size != 0 && offset >= sym.size) {
+            return None;
+        }
+        Some((self.name(sym.name)?, offset))
+    }
+}
+
+#[test]
+fn test_symbol_table() {
+    use core::slice;
+    #[repr(C)]
+    struct Table {
+        symtab: SectionHeader,
+        strtab: SectionHeader,
+        symbols: [Symbol; 2],
+        names: [u8; 12],
+    }
+    let header = |shtype, offset, size, entsize| SectionHeader {
+        name: 0,
+        shtype,
+        flags: 0,
+        addr: 0,
+        offset,
+        size,
+        link: 0,
+        info: 0,
+        addralign: 0,
+        entsize,
+    };
+    let symbol = |name, value, size| Symbol {
+        name,
+        value,
+        size,
+        info: STT_FUNC,
+        other: 0,
+        shndx: 0,
+    };
+    let sym_size = mem::size_of::<Symbol>() as u32;
+    let symtab_offset = 2 * mem::size_of::<SectionHeader>() as u32;
+    let table = Table {
+        symtab: header(SHT_SYMTAB, symtab_offset, 2 * sym_size, sym_size),
+        strtab: header(SHT_STRTAB, symtab_offset + 2 * sym_size, 12, 0),
+        symbols: [symbol(1, 0x1000, 0x10), symbol(6, 0x1100, 0)],
+        names: *b"\0main\0trap\0\0",
+    };
+    let bytes = unsafe {
+        slice::from_raw_parts(&table as *const Table as *const u8, mem::size_of::<Table>())
+    };
+    let symbols = SymbolTable::new(bytes).ok().unwrap();
+    assert_eq!(symbols.lookup(0xfff), None);
+    assert_eq!(symbols.lookup(0x1004), Some(("main", 4)));
+    assert_eq!(symbols.lookup(0x1010), None);
+    assert_eq!(symbols.lookup(0x1180), Some(("trap", 0x80)));
+
+    let broken = Table {
+        symtab: header(SHT_SYMTAB, symtab_offset, 3 * sym_size, sym_size),
+        ..table
+    };
+    let bytes = unsafe {
+        slice::from_raw_parts(
+            &broken as *const Table as *const u8,
+            mem::size_of::<Table>(),
+        )
+    };
+    assert!(SymbolTable::new(bytes).is_err());
+}
+
+#[test]
+fn test_elf_validation() {
+    use core::slice;
+    let valid = ElfHeader {
+        magic: ELF_MAGIC,
+        elf: [
+            ELF_CLASS_32,
+            ELF_DATA_LSB,
+            ELF_VERSION_CURRENT,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ],
+        etype: ET_EXEC,
+        machine: EM_RISCV,
+        version: 1,
+        entry: 0x90000000,
+        phoff: mem::size_of::<ElfHeader>() as u32,
+        shoff: 0,
+        flags: 0,
+        size: mem::size_of::<ElfHeader>() as u16,
+        phentsize: mem::size_of::<ProgramHeader>() as u16,
+        phnum: 0,
+        shentsize: 0,
+        shnum: 0,
+        shstrndx: 0,
+    };
+    let check = |header: &ElfHeader| {
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                header as *const ElfHeader as *const u8,
+                mem::size_of::<ElfHeader>(),
+            )
+        };
+        Elf::new(bytes).map(|e| e.entry())
+    };
+    assert_eq!(check(&valid).ok(), Some(0x90000000));
+    // the file may start at any address, e.g. in the middle of a buffer
+    let mut buf = [0u8; mem::size_of::<ElfHeader>() + 1];
+    buf[1..].copy_from_slice(unsafe {
+        slice::from_raw_parts(
+            &valid as *const ElfHeader as *const u8,
+            mem::size_of::<ElfHeader>(),
+        )
+    });
+    assert_eq!(
+        Elf::new(&buf[1..]).ok().map(|e| e.entry()),
+        Some(0x90000000)
+    );
+    match Elf::new(&buf[1..mem::size_of::<ElfHeader>()]) {
+        Err(ElfError::TooShort) => (),
+        _ => panic!("a truncated header is accepted"),
+    }
+
+    let mut header = ElfHeader {
+        machine: 62,
+        ..valid
+    };
+    assert!(check(&header).err().unwrap().is_unsupported());
+    header = ElfHeader { phnum: 1, ..valid };
+    match check(&header) {
+        Err(ElfError::InvalidProgramHeaders) => (),
+        _ => panic!("program headers out of the file are accepted"),
+    }
+
+    // position independent executables are loaded at USER_PIE_BASE
+    header = ElfHeader {
+        etype: ET_DYN,
+        entry: 0x100,
+        ..valid
+    };
+    assert_eq!(check(&header).ok(), Some(memlayout::USER_PIE_BASE + 0x100));
+
+    // loadable segments must be in the user memory
+    #[repr(C)]
+    struct WithSegment {
+        header: ElfHeader,
+        ph: ProgramHeader,
+    }
+    let segment = |etype, va| WithSegment {
+        header: ElfHeader {
+            etype,
+            phnum: 1,
+            ..valid
+        },
+        ph: ProgramHeader {
+            ptype: PT_LOAD,
+            offset: 0,
+            va,
+            pa: 0,
+            filesz: 0,
+            memsz: paging::PGSIZE as u32,
+            flags: 0,
+            align: paging::PGSIZE as u32,
+        },
+    };
+    let check_segment = |file: &WithSegment| {
+        let bytes = unsafe {
+            slice::from_raw_parts(
+                file as *const WithSegment as *const u8,
+                mem::size_of::<WithSegment>(),
+            )
+        };
+        match Elf::new(bytes) {
+            Ok(_) => Ok(()),
+            Err(ElfError::InvalidSegment) => Err(()),
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    };
+    assert!(check_segment(&segment(ET_EXEC, 0x90000000)).is_ok());
+    assert!(check_segment(&segment(ET_DYN, 0)).is_ok());
+    // the kernel
+    assert!(check_segment(&segment(ET_EXEC, 0x80200000)).is_err());
+    // the page tables
+    assert!(check_segment(&segment(ET_EXEC, 0xffc00000)).is_err());
+    // across the top of the image, into the stack
+    assert!(check_segment(&segment(ET_EXEC, memlayout::USER_STACK_TOP - 0x800)).is_err());
+    let top = memlayout::USER_STACK_TOP - memlayout::USER_PIE_BASE;
+    assert!(check_segment(&segment(ET_DYN, top)).is_err());
+}
@@ -0,0 +1,99 @@
+use core::mem;
+use osmium_fs::File;
+
+pub const N_FDS: usize = 16;
+
+#[derive(Copy, Clone)]
+pub enum Descriptor {
+    Uart,
+    File(File),
+    // the ends of the pipe in the kernel pipe table
+    PipeRead(usize),
+    PipeWrite(usize),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum FdError {
+    BadFileDescriptor,
+    TooManyFiles,
+}
+
+// per process file descriptor table
+#[derive(Copy, Clone)]
+pub struct FdTable {
+    fds: [Option<Descriptor>; N_FDS],
+}
+
+impl FdTable {
+    pub fn new() -> FdTable {
+        FdTable { fds: [None; N_FDS] }
+    }
+
+    // stdin, stdout and stderr are connected to UART
+    pub fn with_stdio() -> FdTable {
+        let mut table = FdTable::new();
+        for i in 0..3 {
+            table.fds[i] = Some(Descriptor::Uart);
+        }
+        table
+    }
+
+    pub fn alloc(&mut self, desc: Descriptor) -> Result<u32, FdError> {
+        for i in 0..N_FDS {
+            if self.fds[i].is_none() {
+                self.fds[i] = Some(desc);
+                return Ok(i as u32);
+            }
+        }
+        Err(FdError::TooManyFiles)
+    }
+
+    pub fn get_mut(&mut self, fd: u32) -> Result<&mut Descriptor, FdError> {
+        match self.fds.get_mut(fd as usize) {
+            Some(Some(desc)) => Ok(desc),
+            _ => Err(FdError::BadFileDescriptor),
+        }
+    }
+
+    pub fn get(&self, fd: u32) -> Result<Descriptor, FdError> {
+        match self.fds.get(fd as usize) {
+            Some(Some(desc)) => Ok(*desc),
+            _ => Err(FdError::BadFileDescriptor),
+        }
+    }
+
+    // put desc at fd. the descriptor which was there is returned
+    pub fn replace(&mut self, fd: u32, desc: Descriptor) -> Result<Option<Descriptor>, FdError> {
+        match self.fds.get_mut(fd as usize) {
+            Some(entry) => Ok(mem::replace(entry, Some(desc))),
+            None => Err(FdError::BadFileDescriptor),
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = Descriptor> + 'a {
+        self.fds.iter().filter_map(|desc| *desc)
+    }
+
+    pub fn close(&mut self, fd: u32) -> Result<Descriptor, FdError> {
+        match self.fds.get_mut(fd as usize) {
+            Some(entry) => entry.take().ok_or(FdError::BadFileDescriptor),
+            None => Err(FdError::BadFileDescriptor),
+        }
+    }
+}
+
+#[test]
+fn test_fd_table() {
+    let mut table = FdTable::with_stdio();
+    assert_eq!(table.alloc(Descriptor::Uart).unwrap(), 3);
+    assert!(table.close(1).is_ok());
+    assert!(table.close(1).is_err());
+    assert_eq!(table.alloc(Descriptor::Uart).unwrap(), 1);
+    assert!(table.get_mut(N_FDS as u32).is_err());
+    assert!(table
+        .replace(1, Descriptor::PipeWrite(0))
+        .unwrap()
+        .is_some());
+    assert!(table.replace(5, Descriptor::PipeRead(0)).unwrap().is_none());
+    assert_eq!(table.iter().count(), 5);
+}
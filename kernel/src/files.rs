@@ -1,109 +1,77 @@
-/* simulates file systems */
-/* This is very poor system in order to create shell */
+/* mounts the file system whose image is embedded in the kernel (see boot.s) */
 use core::slice;
+use osmium_fs::filesystem::FileSystem;
+use osmium_fs::hardware::memory_block_manager::MemoryBlockManager;
+use osmium_fs::{File, FileError, Flag};
 
-const DIRECTORY_FILE_LIMIT: usize = 200;
+pub const MAX_PATH_LENGTH: usize = 256;
+// must be the same size as file_buffer_ptr in boot.s
+const FILE_BUFFER_SIZE: usize = 1048576;
 
 extern "C" {
-    static nop_start: u8;
-    static nop_end: u8;
-    static loop_start: u8;
-    static loop_end: u8;
-    static loopback_start: u8;
-    static loopback_end: u8;
-    static syscaller_start: u8;
-    static syscaller_end: u8;
-    static hello_start: u8;
-    static hello_end: u8;
-    static sh_start: u8;
-    static sh_end: u8;
-    static tic_start: u8;
-    static tic_end: u8;
-    static catch_ball_start: u8;
-    static catch_ball_end: u8;
-/*
-static ls_start: u8;
-static ls_end: u8;
-*/
+    static mut disk_start: u8;
+    static disk_end: u8;
+    static mut file_buffer_ptr: u8;
 }
 
-#[derive(Clone, Copy)]
-struct MemoryFile {
-    pub name: &'static str,
-    pub start: &'static u8,
-    pub end: &'static u8,
-}
-
-impl MemoryFile {
-    pub fn size(&self) -> usize {
-        let start = (self.start as *const u8) as usize;
-        let end = (self.end as *const u8) as usize;
-        end - start
-    }
-}
-
-struct MemoryDirectory {
-    pub name: &'static str,
-    pub files: [Option<MemoryFile>; DIRECTORY_FILE_LIMIT],
-}
+static mut BLOCK_MANAGER: Option<MemoryBlockManager<'static>> = None;
 
-static mut ROOT: MemoryDirectory = MemoryDirectory {
-    name: "",
-    files: [None; DIRECTORY_FILE_LIMIT],
-};
-
-impl MemoryDirectory {
-    pub fn search(&self, name: &str) -> Option<MemoryFile> {
-        for i in 0..DIRECTORY_FILE_LIMIT {
-            match self.files[i] {
-                Some(file) if file.name == name => return Some(file),
-                _ => (),
-            }
+pub fn mount() -> FileSystem<'static> {
+    unsafe {
+        let start = &mut disk_start as *mut u8;
+        let size = (&disk_end as *const u8) as usize - start as usize;
+        BLOCK_MANAGER = Some(MemoryBlockManager::new(slice::from_raw_parts_mut(
+            start, size,
+        )));
+        match BLOCK_MANAGER {
+            Some(ref mut bm) => FileSystem::new(bm),
+            None => panic!("failed to create block manager"),
         }
-        None
     }
 }
 
-pub fn init() {
-    unsafe {
-        let l = [
-            ("/bin/nop", &nop_start, &nop_end),
-            ("/bin/loop", &loop_start, &loop_end),
-            ("/bin/syscaller", &syscaller_start, &syscaller_end),
-            ("/bin/hello", &hello_start, &hello_end),
-            ("/bin/sh", &sh_start, &sh_end),
-            ("/bin/tic", &tic_start, &tic_end),
-            ("/bin/catch_ball", &catch_ball_start, &catch_ball_end),
-        ];
-        for (i, (n, s, e)) in l.iter().enumerate() {
-            ROOT.files[i] = Some(MemoryFile {
-                name: n,
-                start: unsafe { &s },
-                end: unsafe { &e },
-            });
+// file system requires paths which end with '\0'
+fn to_path(name: &[u8]) -> Result<[u8; MAX_PATH_LENGTH], FileError> {
+    if name.len() == 0 || name.len() >= MAX_PATH_LENGTH {
+        return Err(FileError::IllegalPath);
+    }
+    let mut path = [0u8; MAX_PATH_LENGTH];
+    for (i, c) in name.iter().enumerate() {
+        if *c == 0 {
+            return Err(FileError::IllegalPath);
         }
+        path[i] = *c;
     }
+    Ok(path)
 }
 
-pub struct File<'a> {
-    pub name: &'a str,
-    pub bytes: *const [u8],
-    pub size: usize,
+pub fn search(fs: &mut FileSystem, name: &[u8]) -> Result<File, FileError> {
+    let path = to_path(name)?;
+    fs.search(&path)
 }
 
-impl<'a> File<'a> {
-    pub fn new(name: &'a str, bytes: *const [u8], size: usize) -> File<'a> {
-        File { name, bytes, size }
+// create an empty regular file. an existing file is truncated instead, so that nothing of
+// its old contents is left
+pub fn create_or_truncate(fs: &mut FileSystem, name: &[u8]) -> Result<File, FileError> {
+    let path = to_path(name)?;
+    match fs.create(&path, Flag::USER_READ | Flag::USER_WRITE) {
+        Err(FileError::AlreadyExists) => {
+            let mut file = fs.search(&path)?;
+            fs.truncate(&mut file)?;
+            Ok(file)
+        }
+        result => result,
     }
 }
 
-pub fn search<'a>(filename: &'a str) -> Option<File<'a>> {
-    match unsafe { ROOT.search(filename) } {
-        Some(file) => {
-            let size = file.size();
-            let bytes: &[u8] = unsafe { slice::from_raw_parts(file.start as *const u8, size) };
-            Some(File::new(filename, bytes, size))
-        }
-        None => None,
+// read the whole file into the kernel's file buffer.
+// the buffer is shared, so the result must be used before the next call.
+pub fn read_all<'a>(fs: &mut FileSystem, file: &mut File) -> Result<&'a [u8], FileError> {
+    let size = fs.size(file)?;
+    if size > FILE_BUFFER_SIZE {
+        return Err(FileError::TooLarge);
     }
+    let buf = unsafe { slice::from_raw_parts_mut(&mut file_buffer_ptr as *mut u8, size) };
+    fs.read(file, buf, size)?;
+    Ok(buf)
 }
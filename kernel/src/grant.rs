@@ -0,0 +1,96 @@
+use osmium_syscall::perm::Perm;
+use proc::Id;
+
+pub const N_GRANTS: usize = 16;
+
+#[derive(Debug, Copy, Clone)]
+pub enum GrantError {
+    TooManyGrants,
+}
+
+#[derive(Copy, Clone)]
+struct Grant {
+    peer: Id,
+    // the page aligned address in the owner's address space
+    addr: u32,
+    perm: Perm,
+}
+
+// pages which the owner process allows its peers to map by SYS_MMAP
+#[derive(Copy, Clone)]
+pub struct GrantTable {
+    grants: [Option<Grant>; N_GRANTS],
+}
+
+impl GrantTable {
+    pub fn new() -> GrantTable {
+        GrantTable {
+            grants: [None; N_GRANTS],
+        }
+    }
+
+    // allow `peer` to map the page at `addr` with the permissions in `perm`. this replaces
+    // the previous grant of the page to the peer, and the empty `perm` revokes it
+    pub fn grant(&mut self, peer: Id, addr: u32, perm: Perm) -> Result<(), GrantError> {
+        self.revoke(peer, addr);
+        if perm.is_empty() {
+            return Ok(());
+        }
+        for entry in self.grants.iter_mut() {
+            if entry.is_none() {
+                *entry = Some(Grant { peer, addr, perm });
+                return Ok(());
+            }
+        }
+        Err(GrantError::TooManyGrants)
+    }
+
+    fn revoke(&mut self, peer: Id, addr: u32) {
+        for entry in self.grants.iter_mut() {
+            if entry.map_or(false, |g| g.peer == peer && g.addr == addr) {
+                *entry = None;
+            }
+        }
+    }
+
+    // revoke all the grants to `peer`, e.g. when it exits and the id may be reused
+    pub fn revoke_all(&mut self, peer: Id) {
+        for entry in self.grants.iter_mut() {
+            if entry.map_or(false, |g| g.peer == peer) {
+                *entry = None;
+            }
+        }
+    }
+
+    pub fn is_granted(&self, peer: Id, addr: u32, perm: Perm) -> bool {
+        self.grants.iter().any(|entry| {
+            entry.map_or(false, |g| {
+                g.peer == peer && g.addr == addr && g.perm.contains(perm)
+            })
+        })
+    }
+}
+
+#[test]
+fn test_grant_table() {
+    let mut table = GrantTable::new();
+    let rw = Perm::READ | Perm::WRITE;
+    assert!(!table.is_granted(Id(1), 0x1000, Perm::READ));
+    table.grant(Id(1), 0x1000, Perm::READ).unwrap();
+    assert!(table.is_granted(Id(1), 0x1000, Perm::READ));
+    assert!(!table.is_granted(Id(1), 0x1000, rw));
+    assert!(!table.is_granted(Id(2), 0x1000, Perm::READ));
+    assert!(!table.is_granted(Id(1), 0x2000, Perm::READ));
+    // granting again replaces the permissions
+    table.grant(Id(1), 0x1000, rw).unwrap();
+    assert!(table.is_granted(Id(1), 0x1000, rw));
+    table.grant(Id(1), 0x1000, Perm::empty()).unwrap();
+    assert!(!table.is_granted(Id(1), 0x1000, Perm::READ));
+    for i in 0..N_GRANTS as u32 {
+        table.grant(Id(2), i << 12, Perm::READ).unwrap();
+    }
+    assert!(table.grant(Id(3), 0x1000, Perm::READ).is_err());
+    table.revoke_all(Id(2));
+    assert!(!table.is_granted(Id(2), 0, Perm::READ));
+    assert!(table.grant(Id(3), 0x1000, Perm::READ).is_ok());
+}
@@ -0,0 +1,158 @@
+use proc::N_PROCS;
+
+// doubly linked lists of process indices, so push, pop and remove are O(1). the links are
+// kept here, and the ends of the lists are kept by the user (RunQueue has one list for each
+// priority, for example) and passed to each operation. a process is in one list at most.
+
+const NIL: usize = N_PROCS;
+
+#[derive(Copy, Clone)]
+pub struct List {
+    head: usize,
+    tail: usize,
+}
+
+impl List {
+    pub fn new() -> List {
+        List {
+            head: NIL,
+            tail: NIL,
+        }
+    }
+
+    pub fn first(&self) -> Option<usize> {
+        if self.head == NIL {
+            None
+        } else {
+            Some(self.head)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head == NIL
+    }
+}
+
+pub struct IndexList {
+    next: [usize; N_PROCS],
+    prev: [usize; N_PROCS],
+    // the list which the process is in
+    list: [Option<u16>; N_PROCS],
+}
+
+impl IndexList {
+    pub fn new() -> IndexList {
+        IndexList {
+            next: [NIL; N_PROCS],
+            prev: [NIL; N_PROCS],
+            list: [None; N_PROCS],
+        }
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.list[index].is_some()
+    }
+
+    pub fn list_of(&self, index: usize) -> Option<usize> {
+        self.list[index].map(|list| list as usize)
+    }
+
+    // the one after `index` in its list
+    pub fn next(&self, index: usize) -> Option<usize> {
+        match self.next[index] {
+            NIL => None,
+            next => Some(next),
+        }
+    }
+
+    pub fn push_back(&mut self, lists: &mut [List], list: usize, index: usize) {
+        self.insert_before(lists, list, index, None);
+    }
+
+    // put `index` before `before`, which is in lists[list], or at the tail if it is None.
+    // the process is moved if it is in some list
+    pub fn insert_before(
+        &mut self,
+        lists: &mut [List],
+        list: usize,
+        index: usize,
+        before: Option<usize>,
+    ) {
+        self.remove(lists, index);
+        let next = before.unwrap_or(NIL);
+        let prev = if next == NIL {
+            lists[list].tail
+        } else {
+            self.prev[next]
+        };
+        self.prev[index] = prev;
+        self.next[index] = next;
+        if prev == NIL {
+            lists[list].head = index;
+        } else {
+            self.next[prev] = index;
+        }
+        if next == NIL {
+            lists[list].tail = index;
+        } else {
+            self.prev[next] = index;
+        }
+        self.list[index] = Some(list as u16);
+    }
+
+    // take `index` out of its list. nothing happens if it is in no list
+    pub fn remove(&mut self, lists: &mut [List], index: usize) {
+        let list = match self.list_of(index) {
+            Some(list) => list,
+            None => return,
+        };
+        let (prev, next) = (self.prev[index], self.next[index]);
+        if prev == NIL {
+            lists[list].head = next;
+        } else {
+            self.next[prev] = next;
+        }
+        if next == NIL {
+            lists[list].tail = prev;
+        } else {
+            self.prev[next] = prev;
+        }
+        self.list[index] = None;
+    }
+
+    pub fn pop_front(&mut self, lists: &mut [List], list: usize) -> Option<usize> {
+        let index = lists[list].first()?;
+        self.remove(lists, index);
+        Some(index)
+    }
+}
+
+#[test]
+fn test_index_list() {
+    let mut links = IndexList::new();
+    let mut lists = [List::new(); 2];
+    assert_eq!(links.pop_front(&mut lists, 0), None);
+    links.push_back(&mut lists, 0, 3);
+    links.push_back(&mut lists, 0, 1);
+    links.push_back(&mut lists, 1, 2);
+    links.insert_before(&mut lists, 0, 4, Some(1));
+    links.insert_before(&mut lists, 0, 5, Some(3));
+    // 5, 3, 4, 1 and 2
+    assert_eq!(links.list_of(2), Some(1));
+    assert_eq!(links.next(3), Some(4));
+    assert_eq!(links.next(1), None);
+    links.remove(&mut lists, 4);
+    assert!(!links.contains(4));
+    links.remove(&mut lists, 4);
+    // pushing again moves the process to the tail of the list
+    links.push_back(&mut lists, 0, 5);
+    links.push_back(&mut lists, 0, 2);
+    assert!(lists[1].is_empty());
+    assert_eq!(links.pop_front(&mut lists, 0), Some(3));
+    assert_eq!(links.pop_front(&mut lists, 0), Some(1));
+    assert_eq!(links.pop_front(&mut lists, 0), Some(5));
+    assert_eq!(links.pop_front(&mut lists, 0), Some(2));
+    assert_eq!(links.pop_front(&mut lists, 0), None);
+    assert!(lists[0].is_empty());
+    assert_eq!(lists[0].first(), None);
+}
@@ -1,4 +1,11 @@
+use core::cmp;
+use csr;
+use fd;
+use osmium_fs::filesystem::FileSystem;
+use osmium_syscall::errors::SyscallError;
+use osmium_syscall::process::ExitStatus;
 use paging;
+use pipe;
 use proc;
 use trap;
 
@@ -6,8 +13,12 @@ pub struct Kernel<'a> {
     pub mapper: paging::Map<'a>,
     pub allocator: paging::Allocator<'a>,
     pub process_manager: proc::ProcessManager<'a>,
+    pub filesystem: FileSystem<'a>,
+    pub pipes: pipe::PipeTable,
 
     pub current_process: Option<&'a mut proc::Process<'a>>,
+    // mtime when the current process should be preempted
+    pub time_slice_end: u64,
 }
 
 impl<'a> Kernel<'a> {
@@ -22,9 +33,7 @@ impl<'a> Kernel<'a> {
         match self.process_manager.sched() {
             Some(new_p) => {
                 match self.current_process {
-                    Some(ref mut p) => {
-                        p.status = proc::Status::Runnable;
-                    }
+                    Some(ref mut p) => self.process_manager.set_runnable(p),
                     None => (),
                 }
                 self.current_process = Some(unsafe { &mut *new_p });
@@ -40,12 +49,103 @@ impl<'a> Kernel<'a> {
                 self.sched();
                 match self.current_process {
                     Some(ref mut p) => p.run(),
-                    None => panic!("no process is runnable"),
+                    None => idle(),
                 }
             }
         }
     }
 
+    // terminate the current process and release its resources
+    pub fn exit_current_process(
+        &mut self,
+        exit_status: ExitStatus,
+    ) -> Result<(), proc::ProcessError> {
+        let p = match self.current_process.take() {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        for desc in p.fds.iter() {
+            self.close_descriptor(desc);
+        }
+        p.exit(exit_status, &mut self.allocator)?;
+        self.process_manager.release_children(p.id)?;
+        self.process_manager.revoke_grants_to(p.id);
+        // nobody takes the messages of the senders waiting for the queue of p
+        let event = proc::Event::QueueSpace(p.id);
+        while let Some(sender) = self.process_manager.find_blocked_on(event) {
+            sender.finish_syscall(SyscallError::NotFound.to_syscall_result() as u32);
+            self.process_manager.wake_up(sender, event);
+        }
+        // nor sends to the receivers waiting for a message from p
+        let event = proc::Event::MessageFrom(p.id);
+        while let Some(receiver) = self.process_manager.find_blocked_on(event) {
+            receiver.finish_syscall(SyscallError::NotFound.to_syscall_result() as u32);
+            self.process_manager.wake_up(receiver, event);
+        }
+        // nor replies to the callers. SYS_CALL has already advanced the pc
+        let event = proc::Event::Reply(p.id);
+        while let Some(caller) = self.process_manager.find_blocked_on(event) {
+            let error = SyscallError::NotFound.to_syscall_result() as u32;
+            caller.trap_frame.regs.set_syscall_result(error);
+            self.process_manager.wake_up(caller, event);
+        }
+        if p.has_parent() {
+            let parent = self.process_manager.id2proc(p.parent_id)?;
+            self.process_manager.wake_up(parent, proc::Event::ChildExit);
+        } else {
+            self.process_manager.dealloc(p)?;
+        }
+        Ok(())
+    }
+
+    // a descriptor is shared by another fd, e.g. of the child after fork
+    pub fn dup_descriptor(&mut self, desc: fd::Descriptor) {
+        match desc {
+            fd::Descriptor::PipeRead(pipe) => self.pipes.add_reader(pipe),
+            fd::Descriptor::PipeWrite(pipe) => self.pipes.add_writer(pipe),
+            fd::Descriptor::Uart | fd::Descriptor::File(_) => (),
+        }
+    }
+
+    // an fd referring to desc is closed. the processes waiting for the other end of the
+    // pipe are woken up to see EOF or the broken pipe
+    pub fn close_descriptor(&mut self, desc: fd::Descriptor) {
+        match desc {
+            fd::Descriptor::PipeRead(pipe) => {
+                self.pipes.close_reader(pipe);
+                self.process_manager
+                    .wake_up_all(proc::Event::PipeWritable(pipe));
+            }
+            fd::Descriptor::PipeWrite(pipe) => {
+                self.pipes.close_writer(pipe);
+                self.process_manager
+                    .wake_up_all(proc::Event::PipeReadable(pipe));
+            }
+            fd::Descriptor::Uart | fd::Descriptor::File(_) => (),
+        }
+    }
+
+    // put the current process back to the run queue, to run another process
+    pub fn preempt_current_process(&mut self, tf: trap::TrapFrame) {
+        if let Some(p) = self.current_process.take() {
+            p.trap_frame = tf;
+            self.process_manager.set_runnable(p);
+        }
+    }
+
+    // program the timer for the end of the time slice, or the earliest wakeup of sleepers
+    pub fn set_timer(&self) {
+        let deadline = match self.process_manager.next_wakeup() {
+            Some(wakeup) => cmp::min(wakeup, self.time_slice_end),
+            None => self.time_slice_end,
+        };
+        csr::timer::set_deadline(deadline);
+    }
+
+    pub fn current_process_id(&self) -> Option<proc::Id> {
+        self.current_process.as_ref().map(|p| p.id)
+    }
+
     pub fn update_current_process_trap_frame(&mut self, tf: trap::TrapFrame) {
         match self.current_process {
             Some(ref mut p) => p.trap_frame = tf,
@@ -54,6 +154,18 @@ impl<'a> Kernel<'a> {
     }
 }
 
+// wait for an interrupt which makes some process runnable (e.g. the timer waking up a
+// sleeper). the handler starts over on the interrupt stack and never returns here, so the
+// frames of the caller are simply discarded.
+fn idle() -> ! {
+    csr::sstatus::SSTATUS::sie_on();
+    loop {
+        unsafe {
+            asm!("wfi" :::: "volatile");
+        }
+    }
+}
+
 static mut KERNEL: u32 = 0;
 
 pub fn set_kernel_ptr(ptr: *const Kernel) {
@@ -6,23 +6,35 @@
 
 #[macro_use]
 extern crate bitflags;
+extern crate osmium_fs;
 extern crate osmium_syscall;
 
 #[macro_use]
 pub mod uart;
+pub mod args;
+pub mod backtrace;
 pub mod bounded_buffer;
+pub mod coredump;
 pub mod csr;
 pub mod elf;
+pub mod fd;
 pub mod files;
+pub mod grant;
+pub mod indexlist;
 pub mod kernel;
 pub mod memlayout;
 pub mod memutil;
 #[macro_use]
 pub mod paging;
+pub mod pipe;
 pub mod proc;
+pub mod runqueue;
+pub mod sleepqueue;
 pub mod syscall;
 pub mod trap;
+pub mod usermem;
 pub mod utils;
+pub mod waitqueue;
 
 use core::panic::PanicInfo;
 use csr::satp;
@@ -47,6 +59,7 @@ struct BootAlloc<'a> {
     pub procs: &'a mut [proc::Process<'a>; proc::N_PROCS],
     pub proc_pages: &'a mut [paging::PageTable; proc::N_PROCS],
     pub proc_tmp_pages: &'a mut [paging::PageTable; proc::N_PROCS],
+    pub frame_ref_counts: &'a mut [u16; paging::N_FRAMES],
     pub kernel: &'a mut kernel::Kernel<'a>,
 }
 
@@ -65,6 +78,10 @@ fn boot_alloc<'a>() -> (u64, BootAlloc<'a>) {
     let end = end + (proc::N_PROCS as u64) * (proc::Process::size_of() as u64);
     let end = utils::round_up(end, paging::PGSIZE as u64);
 
+    let frame_ref_counts = unsafe { &mut *(end as *mut [u16; paging::N_FRAMES]) };
+    let end = end + (paging::N_FRAMES * 2) as u64;
+    let end = utils::round_up(end, paging::PGSIZE as u64);
+
     let kernel = unsafe { &mut *(end as *mut kernel::Kernel) };
     let end = end + (kernel::Kernel::size_of() as u64);
     let end = utils::round_up(end, paging::PGSIZE as u64);
@@ -75,6 +92,7 @@ fn boot_alloc<'a>() -> (u64, BootAlloc<'a>) {
             procs,
             proc_pages,
             proc_tmp_pages,
+            frame_ref_counts,
             kernel,
         },
     )
@@ -108,7 +126,8 @@ pub extern "C" fn __start_rust() -> ! {
         }
         false
     };
-    let mut allocator = unsafe { paging::Allocator::new(kernel_frames, &is_used) };
+    let mut allocator =
+        unsafe { paging::Allocator::new(kernel_frames, allocated.frame_ref_counts, &is_used) };
     println!("allocator created");
 
     println!("envs start with {:x}", get_kernel_end_addr());
@@ -164,13 +183,19 @@ pub extern "C" fn __start_rust() -> ! {
         allocated.proc_pages,
         allocated.proc_tmp_pages,
     );
+
+    println!("setting up file system");
+    let filesystem = files::mount();
     // finished initializing a kernel
 
     let kernel = kernel::Kernel {
         mapper,
         allocator,
         process_manager,
+        filesystem,
+        pipes: pipe::PipeTable::new(),
         current_process: None,
+        time_slice_end: 0,
     };
     println!("setting kernel");
 
@@ -200,22 +225,28 @@ pub extern "C" fn __start_rust() -> ! {
         Err(e) => panic!("failed to create process: {}", e),
     };
 
-    println!("setting up file system");
-    files::init();
-
-    let sh_file = match files::search("/bin/sh") {
-        Some(file) => file,
-        None => panic!("failed to find sh"),
+    let mut sh_file = match files::search(&mut kernel.filesystem, b"/bin/sh") {
+        Ok(file) => file,
+        Err(e) => panic!("failed to find sh: {:?}", e),
+    };
+    let sh_bytes = match files::read_all(&mut kernel.filesystem, &mut sh_file) {
+        Ok(bytes) => bytes,
+        Err(e) => panic!("failed to read sh: {:?}", e),
     };
 
-    println!("sh_file bytes: {}", sh_file.bytes as *const u8 as usize);
-    let nop_elf = elf::Elf::new(sh_file.bytes).expect("failed to parse ELF");
+    let nop_elf = elf::Elf::new(sh_bytes).expect("failed to parse ELF");
 
     match process.load_elf(&nop_elf, &mut kernel.allocator) {
         Ok(()) => (),
         Err(e) => panic!("failed to load elf: {}", e),
     };
-    let tf = trap::TrapFrame::new(nop_elf.elf.entry, memlayout::USER_STACK_BOTTOMN);
+    let sp;
+    address_space!(process, {
+        sp = args::Args::new()
+            .push_to_user_stack(&mut process.mapper, &mut kernel.allocator)
+            .expect("failed to setup the user stack");
+    });
+    let tf = trap::TrapFrame::new(nop_elf.entry(), sp);
     process.set_trap_frame(tf);
 
     kernel.current_process = Some(process);
@@ -227,6 +258,8 @@ pub extern "C" fn __start_rust() -> ! {
 #[no_mangle]
 pub fn panic(info: &PanicInfo) -> ! {
     println!("{}", info);
+    trap::dump_current_trap();
+    backtrace::print_backtrace();
     loop {}
 }
 
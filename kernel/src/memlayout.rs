@@ -2,4 +2,9 @@ use paging;
 
 pub const USER_STACK_TOP: u32 = 0xe0000000;
 pub const USER_STACK_SIZE: u32 = paging::PGSIZE as u32 * 16;
+// load address of position independent executables
+pub const USER_PIE_BASE: u32 = 0x90000000;
+// executables are loaded in [USER_IMAGE_START, USER_IMAGE_END), below the stack
+pub const USER_IMAGE_START: u32 = paging::USER_MEMORY_BASE as u32;
+pub const USER_IMAGE_END: u32 = USER_STACK_TOP;
 pub const USER_STACK_BOTTOMN: u32 = USER_STACK_TOP + USER_STACK_SIZE - 4;
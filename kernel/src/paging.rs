@@ -14,6 +14,8 @@ pub const N_PAGE_ENTRY: usize = PGSIZE / PAGE_ENTRY_SIZE;
 pub const TMP_PAGE_ENTRY: usize = N_PAGE_ENTRY - 1;
 pub const USER_MEMORY_BASE: usize = 0x80400000;
 pub const USER_MEMORY_SIZE: usize = (usize::max_value() - USER_MEMORY_BASE) + 1;
+// the page tables are mapped at the last entry of the page directory
+pub const PAGE_TABLE_WINDOW: usize = TMP_PAGE_ENTRY * N_PAGE_ENTRY * PGSIZE;
 
 extern "C" {
     static tmp_reserved_page: u8;
@@ -164,13 +166,14 @@ impl Page {
         let addr = VirtAddr::new((vpns[1] << 22) | (vpns[0] << 12));
         Page { ref_count: 0, addr }
     }
+    // pages which overlap [addr, addr + size)
     pub fn range(addr: VirtAddr, size: u32) -> PageRange {
-        let end = addr.page_start_addr() + size;
+        let end = addr.to_u32() + size;
         let md = end % (PGSIZE as u32);
         let pad = if md == 0 { 0 } else { (PGSIZE as u32) - md };
         PageRange {
             start: Page::from_addr(addr),
-            end: Page::from_addr(VirtAddr::new(addr.page_start_addr() + size + pad)),
+            end: Page::from_addr(VirtAddr::new(end + pad)),
         }
     }
     pub fn next_page(&self) -> Page {
@@ -201,7 +204,7 @@ impl Iterator for PageRange {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Frame {
     addr: PhysAddr,
 }
@@ -219,11 +222,18 @@ impl Frame {
     pub fn phys_addr(&self) -> PhysAddr {
         self.addr
     }
+
+    fn index(&self) -> usize {
+        (self.addr.floor_pgsize().0 >> LOG_PGSIZE) as usize
+    }
 }
 
 // Fixed size memory allocator by 'stack-like' simple data structure
+// ref_counts holds how many page table entries refer to each frame.
+// frames which are not mapped by Map::map (kernel frames, page tables) have 0.
 pub struct Allocator<'a> {
     frames: &'a mut [Frame; N_FRAMES],
+    ref_counts: &'a mut [u16; N_FRAMES],
     stack: usize,
 }
 
@@ -253,10 +263,15 @@ impl fmt::Display for PageError {
 }
 
 impl<'a> Allocator<'a> {
-    pub unsafe fn new(frames: *mut u32, is_used: &Fn(usize) -> bool) -> Allocator<'a> {
+    pub unsafe fn new(
+        frames: *mut u32,
+        ref_counts: &'a mut [u16; N_FRAMES],
+        is_used: &Fn(usize) -> bool,
+    ) -> Allocator<'a> {
         let frames = &mut *(frames as *mut [Frame; N_FRAMES]);
         let mut stack = 0;
         for i in 0..N_FRAMES {
+            ref_counts[i] = 0;
             if is_used(i * PGSIZE) {
                 continue;
             }
@@ -267,7 +282,11 @@ impl<'a> Allocator<'a> {
             stack += 1;
         }
         dprintln!("N_FRAMES: {}, stack: {}", N_FRAMES, stack);
-        Allocator { frames, stack }
+        Allocator {
+            frames,
+            ref_counts,
+            stack,
+        }
     }
     pub fn alloc(&mut self) -> Result<Frame, PageError> {
         if self.stack == 0 {
@@ -287,6 +306,31 @@ impl<'a> Allocator<'a> {
             Ok(())
         }
     }
+
+    pub fn ref_count(&self, frame: Frame) -> u16 {
+        self.ref_counts[frame.index()]
+    }
+
+    pub fn inc_ref(&mut self, frame: Frame) {
+        self.ref_counts[frame.index()] += 1;
+    }
+
+    // the frame is returned to the allocator when no one refers to it
+    pub fn dec_ref(&mut self, frame: Frame) -> Result<(), PageError> {
+        let index = frame.index();
+        match self.ref_counts[index] {
+            // not managed by reference counting
+            0 => Ok(()),
+            1 => {
+                self.ref_counts[index] = 0;
+                self.dealloc(frame)
+            }
+            _ => {
+                self.ref_counts[index] -= 1;
+                Ok(())
+            }
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -445,6 +489,62 @@ impl<'a> Map<'a> {
         Ok(())
     }
 
+    // release all the user pages and page tables for them
+    pub fn free_user_memory(&mut self, allocator: &mut Allocator) -> Result<(), PageError> {
+        let old_satp = satp::SATP::read();
+        satp::SATP::set_ppn(self.ppn());
+
+        let user_entry = USER_MEMORY_BASE / (PGSIZE * N_PAGE_ENTRY);
+        for i in user_entry..(N_PAGE_ENTRY - 1) {
+            if !self.dir[i].is_valid() {
+                continue;
+            }
+            let table = Map::get_vpn1_page_table(i);
+            for j in 0..N_PAGE_ENTRY {
+                if table[j].flag().intersects(Flag::VALID | Flag::COW) {
+                    let frame = table[j].frame();
+                    table[j].unset_frame();
+                    allocator.dec_ref(frame)?;
+                }
+            }
+            let table_frame = self.dir[i].frame();
+            self.dir[i].unset_frame();
+            self.tmp_page[i].unset_frame();
+            allocator.dealloc(table_frame)?;
+        }
+        old_satp.commit();
+        Ok(())
+    }
+
+    // call f on every mapped user page, with satp set to self
+    pub fn for_each_user_page<E, F>(&self, mut f: F) -> Result<(), E>
+    where
+        F: FnMut(Page, Flag) -> Result<(), E>,
+    {
+        let old_satp = satp::SATP::read();
+        satp::SATP::set_ppn(self.ppn());
+
+        let mut result = Ok(());
+        let user_entry = USER_MEMORY_BASE / (PGSIZE * N_PAGE_ENTRY);
+        'outer: for i in user_entry..(N_PAGE_ENTRY - 1) {
+            if !self.dir[i].is_valid() {
+                continue;
+            }
+            let table = Map::get_vpn1_page_table(i);
+            for j in 0..N_PAGE_ENTRY {
+                let flag = table[j].flag();
+                if flag.contains(Flag::VALID | Flag::USER) {
+                    result = f(Page::from_vpns([j as u32, i as u32]), flag);
+                    if result.is_err() {
+                        break 'outer;
+                    }
+                }
+            }
+        }
+        old_satp.commit();
+        result
+    }
+
     fn vpn1_page(page: Page) -> Page {
         Page::from_vpns([page.vpn1(), TMP_PAGE_ENTRY as u32])
     }
@@ -549,7 +649,24 @@ impl<'a> Map<'a> {
         flag: Flag,
         allocator: &mut Allocator,
     ) -> Result<(), PageError> {
-        self.map_inner(page, frame, flag, allocator, false)
+        let old_frame = if self.is_mapped(page) {
+            Some(self.frame(page)?)
+        } else {
+            None
+        };
+        self.map_inner(page, frame, flag, allocator, false)?;
+        match old_frame {
+            // only flags are changed
+            Some(old_frame) if old_frame == frame => Ok(()),
+            Some(old_frame) => {
+                allocator.inc_ref(frame);
+                allocator.dec_ref(old_frame)
+            }
+            None => {
+                allocator.inc_ref(frame);
+                Ok(())
+            }
+        }
     }
 
     fn get_table_entry(&self, page: Page) -> Result<&'a PageTableEntry, PageError> {
@@ -572,12 +689,22 @@ impl<'a> Map<'a> {
         let mut flag = self.flag(page)?;
         flag.remove(Flag::COW);
         flag.insert(Flag::WRITE);
-        let frame = allocator.alloc()?;
-        dprintln!("got flag, frame");
 
         let old_satp = satp::SATP::read();
         satp::SATP::set_ppn(self.ppn());
 
+        // the last owner takes the frame without copying
+        let old_frame = self.frame(page)?;
+        if allocator.ref_count(old_frame) == 1 {
+            dprintln!("take ownership");
+            self.map(page, old_frame, flag, allocator)?;
+            old_satp.commit();
+            return Ok(());
+        }
+
+        let frame = allocator.alloc()?;
+        dprintln!("got flag, frame");
+
         let tmp_page = get_tmp_page_addr();
 
         dprintln!("tmp page");
@@ -600,20 +727,47 @@ impl<'a> Map<'a> {
         self.map(page, frame, flag, allocator)?;
 
         dprintln!("unmapping");
-        self.unmap(tmp_page)?;
+        self.unmap(tmp_page, allocator)?;
 
         old_satp.commit();
         Ok(())
     }
 
-    pub fn unmap(&mut self, page: Page) -> Result<(), PageError> {
-        // TODO do frame handling(if frame user is none, dealloc it)
+    // change the flag of a mapped page
+    pub fn protect(&mut self, page: Page, flag: Flag) -> Result<(), PageError> {
+        if !self.is_mapped(page) {
+            return Err(PageError::PageIsNotMapped);
+        }
         let vpn1 = self.get_next_table_mut(page)?;
         let entry = &mut vpn1[page.vpn0() as usize];
-        entry.unset_frame();
+        let frame = entry.frame();
+        entry.set_frame(frame, flag);
         Ok(())
     }
 
+    fn unlink(&mut self, page: Page) -> Result<Frame, PageError> {
+        let vpn1 = self.get_next_table_mut(page)?;
+        let entry = &mut vpn1[page.vpn0() as usize];
+        let frame = entry.frame();
+        entry.unset_frame();
+        Ok(frame)
+    }
+
+    pub fn is_mapped(&self, page: Page) -> bool {
+        match self.flag(page) {
+            Ok(flag) => flag.intersects(Flag::VALID | Flag::COW),
+            Err(_) => false,
+        }
+    }
+
+    pub fn unmap(&mut self, page: Page, allocator: &mut Allocator) -> Result<(), PageError> {
+        if !self.is_mapped(page) {
+            return Err(PageError::PageIsNotMapped);
+        }
+        let frame = self.unlink(page)?;
+        allocator.dec_ref(frame)
+    }
+
     pub fn alloc(
         &mut self,
         virt_addr: VirtAddr,
@@ -635,13 +789,26 @@ impl<'a> Map<'a> {
         println!("tmp: \n{}", self.tmp_page);
     }
 
+    // unmap user pages in [virt_addr, virt_addr + size) and release their frames
     pub fn free(
         &mut self,
         virt_addr: VirtAddr,
         size: u32,
         allocator: &mut Allocator,
     ) -> Result<(), PageError> {
-        unimplemented!()
+        // check all the pages first in order not to leave the region half freed
+        for page in Page::range(virt_addr, size) {
+            if !self.is_mapped(page) {
+                return Err(PageError::PageIsNotMapped);
+            }
+            if !self.flag(page)?.contains(Flag::USER) {
+                return Err(PageError::IllegalAddress);
+            }
+        }
+        for page in Page::range(virt_addr, size) {
+            self.unmap(page, allocator)?;
+        }
+        Ok(())
     }
 
     fn map_region_inner(
@@ -742,15 +909,10 @@ impl<'a> Map<'a> {
     }
 
     pub fn check_perm(&self, addr: VirtAddr, flag: Flag) -> bool {
-        let p = Page::from_addr(addr);
-
-        let t = Map::vpn1_page(p);
-        let ptr: *mut PageTable = t.base_addr().as_mut_ptr();
-        let table = unsafe { &mut *ptr };
-        let pte = &mut table[p.vpn0() as usize];
-
-        let pte_flag = pte.flag();
-        pte_flag.contains(flag)
+        match self.flag(Page::from_addr(addr)) {
+            Ok(pte_flag) => pte_flag.contains(flag),
+            Err(_) => false,
+        }
     }
 }
 
@@ -0,0 +1,152 @@
+use core::cmp;
+
+pub const N_PIPES: usize = 32;
+// the number of bytes which a pipe holds before writers block
+pub const PIPE_SIZE: usize = 512;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PipeError {
+    TooManyPipes,
+    // nothing to read, but some writer is left
+    Empty,
+    // no space to write
+    Full,
+    // all the readers are closed
+    BrokenPipe,
+}
+
+// a bounded byte stream between processes. it is freed when both ends are closed
+#[derive(Copy, Clone)]
+struct Pipe {
+    buffer: [u8; PIPE_SIZE],
+    head: usize,
+    len: usize,
+    // the number of descriptors referring to each end
+    readers: usize,
+    writers: usize,
+}
+
+impl Pipe {
+    fn is_free(&self) -> bool {
+        self.readers == 0 && self.writers == 0
+    }
+}
+
+pub struct PipeTable {
+    pipes: [Pipe; N_PIPES],
+}
+
+impl PipeTable {
+    pub fn new() -> PipeTable {
+        PipeTable {
+            pipes: [Pipe {
+                buffer: [0; PIPE_SIZE],
+                head: 0,
+                len: 0,
+                readers: 0,
+                writers: 0,
+            }; N_PIPES],
+        }
+    }
+
+    // create a pipe with one reader and one writer
+    pub fn alloc(&mut self) -> Result<usize, PipeError> {
+        for (i, pipe) in self.pipes.iter_mut().enumerate() {
+            if pipe.is_free() {
+                pipe.head = 0;
+                pipe.len = 0;
+                pipe.readers = 1;
+                pipe.writers = 1;
+                return Ok(i);
+            }
+        }
+        Err(PipeError::TooManyPipes)
+    }
+
+    // returns 0 at the end of the stream, that is, if it is empty and no writer is left
+    pub fn read(&mut self, index: usize, buf: &mut [u8]) -> Result<usize, PipeError> {
+        let pipe = &mut self.pipes[index];
+        if buf.len() == 0 {
+            return Ok(0);
+        }
+        if pipe.len == 0 {
+            return if pipe.writers == 0 {
+                Ok(0)
+            } else {
+                Err(PipeError::Empty)
+            };
+        }
+        let n = cmp::min(buf.len(), pipe.len);
+        for c in buf.iter_mut().take(n) {
+            *c = pipe.buffer[pipe.head];
+            pipe.head = (pipe.head + 1) % PIPE_SIZE;
+        }
+        pipe.len -= n;
+        Ok(n)
+    }
+
+    // write as many bytes as the space allows
+    pub fn write(&mut self, index: usize, buf: &[u8]) -> Result<usize, PipeError> {
+        let pipe = &mut self.pipes[index];
+        if pipe.readers == 0 {
+            return Err(PipeError::BrokenPipe);
+        }
+        if buf.len() == 0 {
+            return Ok(0);
+        }
+        if pipe.len == PIPE_SIZE {
+            return Err(PipeError::Full);
+        }
+        let n = cmp::min(buf.len(), PIPE_SIZE - pipe.len);
+        for c in buf.iter().take(n) {
+            pipe.buffer[(pipe.head + pipe.len) % PIPE_SIZE] = *c;
+            pipe.len += 1;
+        }
+        Ok(n)
+    }
+
+    pub fn add_reader(&mut self, index: usize) {
+        self.pipes[index].readers += 1;
+    }
+
+    pub fn add_writer(&mut self, index: usize) {
+        self.pipes[index].writers += 1;
+    }
+
+    pub fn close_reader(&mut self, index: usize) {
+        self.pipes[index].readers -= 1;
+    }
+
+    pub fn close_writer(&mut self, index: usize) {
+        self.pipes[index].writers -= 1;
+    }
+}
+
+#[test]
+fn test_pipe() {
+    let mut pipes = PipeTable::new();
+    let p = pipes.alloc().unwrap();
+    let mut buf = [0u8; 4];
+    assert_eq!(pipes.read(p, &mut buf), Err(PipeError::Empty));
+    assert_eq!(pipes.write(p, b"abc"), Ok(3));
+    assert_eq!(pipes.read(p, &mut buf[..2]), Ok(2));
+    assert_eq!(&buf[..2], b"ab");
+    // fill up the buffer across its end
+    let data = [b'x'; PIPE_SIZE];
+    assert_eq!(pipes.write(p, &data), Ok(PIPE_SIZE - 1));
+    assert_eq!(pipes.write(p, b"y"), Err(PipeError::Full));
+    assert_eq!(pipes.read(p, &mut buf), Ok(4));
+    assert_eq!(&buf, b"cxxx");
+    // the rest is still readable after the writer is closed
+    pipes.close_writer(p);
+    let mut rest = [0u8; PIPE_SIZE];
+    assert_eq!(pipes.read(p, &mut rest), Ok(PIPE_SIZE - 4));
+    assert_eq!(pipes.read(p, &mut rest), Ok(0));
+    // the slot is reused after the reader is closed too
+    pipes.add_reader(p);
+    pipes.close_reader(p);
+    pipes.close_reader(p);
+    assert_eq!(pipes.alloc(), Ok(p));
+    pipes.close_reader(p);
+    assert_eq!(pipes.write(p, b"z"), Err(PipeError::BrokenPipe));
+}
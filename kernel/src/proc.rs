@@ -1,15 +1,27 @@
 use bounded_buffer as bb;
+use core::cmp;
 use core::fmt;
-use csr::CSRRead;
+use core::mem;
+use core::ptr;
+use core::slice;
 use elf;
+use fd;
+use grant;
 use memlayout;
 use memutil;
+use osmium_syscall::errors::SyscallError;
+use osmium_syscall::ipc::DEFAULT_QUEUE_CAPACITY;
+use osmium_syscall::process::{ExitStatus, DEFAULT_PRIORITY};
 use paging;
+use runqueue::RunQueue;
 use satp;
+use sleepqueue::SleepQueue;
 use trap;
-use utils;
+use waitqueue::WaitQueue;
 
 pub const N_PROCS: usize = 1024;
+// the slot which SYS_EXECVE builds a new image in. it is never allocated to a process
+const SPARE_INDEX: usize = 0;
 
 pub enum Type {
     User,
@@ -39,6 +51,7 @@ impl Status {
 pub enum ProcessError {
     FailedToCreateProcess,
     FailedToMap(paging::PageError),
+    InvalidElf(elf::ElfError),
     ProgramError(&'static str),
     NoSuchProcess,
     QueueIsFull,
@@ -50,6 +63,7 @@ impl ProcessError {
         match self {
             ProcessError::FailedToCreateProcess => "failed to create process",
             ProcessError::FailedToMap(_) => "failed to map",
+            ProcessError::InvalidElf(_) => "invalid elf",
             ProcessError::ProgramError(s) => s,
             ProcessError::NoSuchProcess => "no such process",
             ProcessError::QueueIsEmpty => "queue is empty",
@@ -73,10 +87,82 @@ impl Id {
     }
 }
 
+// an event which a NotRunnable process is waiting for
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    ChildExit,
+    Timer,
+    // a message is sent to the process
+    Message,
+    // a message is sent to the process by Id
+    MessageFrom(Id),
+    // the message queue of the process gets a space
+    QueueSpace(Id),
+    // the process replies to SYS_CALL
+    Reply(Id),
+    // the pipe gets data, or loses its last writer
+    PipeReadable(usize),
+    // the pipe gets a space, or loses its last reader
+    PipeWritable(usize),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Body {
+    // sent by SYS_SEND
+    Word(u32),
+    // sent by SYS_SEND_BUF. a kernel frame holding the bytes, and their length
+    Buffer(paging::Frame, u32),
+    // sent by SYS_CALL. the sender waits for the reply
+    Call(u32),
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Message {
     pub id: Id,
-    pub data: u32,
+    pub body: Body,
+}
+
+impl Message {
+    pub fn word(id: Id, data: u32) -> Message {
+        Message {
+            id,
+            body: Body::Word(data),
+        }
+    }
+
+    pub fn len(&self) -> u32 {
+        match self.body {
+            Body::Word(_) | Body::Call(_) => mem::size_of::<u32>() as u32,
+            Body::Buffer(_, len) => len,
+        }
+    }
+
+    // copy the body to the head of buf as much as possible
+    pub fn copy_to(&self, buf: &mut [u8]) {
+        let word;
+        let bytes = match self.body {
+            Body::Word(data) | Body::Call(data) => {
+                word = data.to_ne_bytes();
+                &word[..]
+            }
+            Body::Buffer(frame, len) => unsafe {
+                slice::from_raw_parts(
+                    frame.phys_addr().kern_virt_addr().as_ptr::<u8>(),
+                    len as usize,
+                )
+            },
+        };
+        let size = cmp::min(bytes.len(), buf.len());
+        buf[..size].copy_from_slice(&bytes[..size]);
+    }
+
+    // release the frame of the body after it is received
+    pub fn free(self, allocator: &mut paging::Allocator) -> Result<(), paging::PageError> {
+        match self.body {
+            Body::Word(_) | Body::Call(_) => Ok(()),
+            Body::Buffer(frame, _) => allocator.dealloc(frame),
+        }
+    }
 }
 
 #[repr(C)]
@@ -87,8 +173,16 @@ pub struct Process<'a> {
     index: usize,
     pub proc_type: Type,
     pub status: Status,
+    // smaller is scheduled first. change it through ProcessManager::set_priority
+    pub priority: u32,
     pub trap_frame: trap::TrapFrame,
-    pub exit_code: u32,
+    pub exit_status: ExitStatus,
+    pub blocked_on: Option<Event>,
+    // the sender of the last SYS_CALL received, which SYS_REPLY_RECV replies to
+    pub last_caller: Option<Id>,
+    pub fds: fd::FdTable,
+    // pages which the process allows others to map
+    pub grants: grant::GrantTable,
     message_queue: bb::BoundedBuffer<Message>,
 }
 
@@ -99,8 +193,36 @@ impl<'a> Process<'a> {
         self.parent_id = id;
         self.proc_type = Type::User;
         self.status = Status::Free;
+        self.priority = DEFAULT_PRIORITY;
         self.trap_frame = trap::TrapFrame::new(0, 0);
-        self.message_queue = bb::BoundedBuffer::new(Message { id, data: 0 });
+        self.exit_status = ExitStatus::exited(0);
+        self.blocked_on = None;
+        self.last_caller = None;
+        self.fds = fd::FdTable::new();
+        self.grants = grant::GrantTable::new();
+        self.message_queue =
+            bb::BoundedBuffer::with_capacity(Message::word(id, 0), DEFAULT_QUEUE_CAPACITY);
+    }
+
+    // reset the state for reuse of this slot
+    fn clear(&mut self) {
+        let id = self.id;
+        self.parent_id = id;
+        self.status = Status::Free;
+        self.priority = DEFAULT_PRIORITY;
+        self.trap_frame = trap::TrapFrame::new(0, 0);
+        self.exit_status = ExitStatus::exited(0);
+        self.blocked_on = None;
+        self.last_caller = None;
+        self.fds = fd::FdTable::new();
+        self.grants = grant::GrantTable::new();
+        self.message_queue =
+            bb::BoundedBuffer::with_capacity(Message::word(id, 0), DEFAULT_QUEUE_CAPACITY);
+    }
+
+    // a process whose parent_id is itself has no one to collect its exit code
+    pub fn has_parent(&self) -> bool {
+        self.parent_id != self.id
     }
     // dont touch without ProcessManager
     pub unsafe fn set_index(&mut self, index: usize) {
@@ -116,6 +238,7 @@ impl<'a> Process<'a> {
 
     pub fn create(&mut self, mapper: &mut paging::Map) -> Result<(), ProcessError> {
         mapper.clone_dir(&mut self.mapper);
+        self.fds = fd::FdTable::with_stdio();
         Ok(())
     }
 
@@ -134,8 +257,21 @@ impl<'a> Process<'a> {
         flag: paging::Flag,
         allocator: &mut paging::Allocator,
     ) -> Result<(), ProcessError> {
-        let old_satp = satp::SATP::read_csr();
-        satp::SATP::set_ppn(self.ppn());
+        let result;
+        address_space!(self, {
+            result = self.map_region(va, size, flag, allocator);
+        });
+        result
+    }
+
+    // satp must point to this address space
+    fn map_region(
+        &mut self,
+        va: paging::VirtAddr,
+        size: usize,
+        flag: paging::Flag,
+        allocator: &mut paging::Allocator,
+    ) -> Result<(), ProcessError> {
         for page in paging::Page::range(va, size as u32) {
             match allocator.alloc() {
                 Ok(frame) => {
@@ -148,48 +284,98 @@ impl<'a> Process<'a> {
                 Err(e) => return Err(ProcessError::FailedToMap(e)),
             }
         }
-        satp::SATP::set_ppn(old_satp);
         Ok(())
     }
+
     pub fn load_elf(
         &mut self,
         elf_file: &elf::Elf,
         allocator: &mut paging::Allocator,
     ) -> Result<(), ProcessError> {
-        let old_satp = satp::SATP::read_csr();
-        satp::SATP::set_ppn(self.ppn());
+        // satp is restored even if loading fails
+        let result;
+        address_space!(self, {
+            result = self.load_segments(elf_file, allocator);
+        });
+        result
+    }
 
-        for program in elf_file.programs() {
-            self.region_alloc(
-                program.virt_addr,
-                utils::round_up(program.mem_size as u64, paging::PGSIZE as u64) as usize,
-                paging::Flag::EXEC
-                    | paging::Flag::USER
-                    | paging::Flag::READ
-                    | paging::Flag::WRITE
-                    | paging::Flag::VALID,
-                allocator,
-            )?;
-            let region = program.virt_addr.as_mut_ptr();
+    // satp must point to this address space
+    fn load_segments(
+        &mut self,
+        elf_file: &elf::Elf,
+        allocator: &mut paging::Allocator,
+    ) -> Result<(), ProcessError> {
+        // pages are writable until all the segments are copied, since a page may be
+        // shared by two segments
+        let load_flag =
+            paging::Flag::USER | paging::Flag::READ | paging::Flag::WRITE | paging::Flag::VALID;
+        for (i, program) in elf_file.programs().enumerate() {
+            for page in program.pages() {
+                // the page is shared with a segment loaded before
+                if elf_file
+                    .programs()
+                    .take(i)
+                    .any(|other| other.is_on_page(page))
+                {
+                    continue;
+                }
+                // never write to a page mapped by someone else, e.g. of the kernel
+                if self.mapper.is_mapped(page) {
+                    return Err(ProcessError::FailedToMap(paging::PageError::AlreadyMapped));
+                }
+                let frame = allocator.alloc().map_err(ProcessError::FailedToMap)?;
+                self.mapper
+                    .map(page, frame, load_flag, allocator)
+                    .map_err(ProcessError::FailedToMap)?;
+                unsafe {
+                    memutil::memset(page.base_addr().as_mut_ptr(), 0, paging::PGSIZE);
+                }
+            }
+            let region: *mut u8 = program.virt_addr.as_mut_ptr();
             unsafe {
+                memutil::memcpy(region, program.data, program.file_size);
+                // bss
                 memutil::memset(
-                    region,
+                    region.add(program.file_size),
                     0,
-                    utils::round_up(program.mem_size as u64, paging::PGSIZE as u64) as usize,
+                    program.mem_size - program.file_size,
                 );
-                memutil::memcpy(region, program.data, program.file_size);
+            }
+        }
+
+        for reloc in elf_file.relocations().map_err(ProcessError::InvalidElf)? {
+            let reloc = reloc.map_err(ProcessError::InvalidElf)?;
+            unsafe {
+                ptr::write_unaligned(reloc.addr.as_mut_ptr::<u32>(), reloc.value);
+            }
+        }
+
+        for program in elf_file.programs() {
+            for page in program.pages() {
+                let mut flag = paging::Flag::USER | paging::Flag::VALID;
+                for other in elf_file.programs() {
+                    if other.is_on_page(page) {
+                        flag |= other.flag;
+                    }
+                }
+                // a writable page must be readable
+                if flag.contains(paging::Flag::WRITE) {
+                    flag |= paging::Flag::READ;
+                }
+                self.mapper
+                    .protect(page, flag)
+                    .map_err(ProcessError::FailedToMap)?;
             }
         }
 
         // alloc stack
-        self.region_alloc(
+        self.map_region(
             paging::VirtAddr::new(memlayout::USER_STACK_TOP),
             memlayout::USER_STACK_SIZE as usize,
             paging::Flag::VALID | paging::Flag::READ | paging::Flag::WRITE | paging::Flag::USER,
             allocator,
         )?;
-
-        satp::SATP::set_ppn(old_satp);
         Ok(())
     }
 
@@ -202,13 +388,44 @@ impl<'a> Process<'a> {
         }
     }
 
-    pub fn exit(&mut self, exit_code: u32) {
+    // complete the syscall which the process is blocked in, instead of issuing it again
+    pub fn finish_syscall(&mut self, result: u32) {
+        self.trap_frame.pc += 4;
+        self.trap_frame.regs.set_syscall_result(result);
+    }
+
+    // stop scheduling this process until `event` happens
+    pub fn block(&mut self, event: Event) {
+        self.status = Status::NotRunnable;
+        self.blocked_on = Some(event);
+    }
+
+    // release resources of the process. the slot itself is kept until its parent collects
+    // the exit code
+    pub fn exit(
+        &mut self,
+        exit_status: ExitStatus,
+        allocator: &mut paging::Allocator,
+    ) -> Result<(), ProcessError> {
         self.status = Status::Zonmbie;
-        self.exit_code = exit_code;
+        self.exit_status = exit_status;
+        self.fds = fd::FdTable::new();
+        self.grants = grant::GrantTable::new();
+        // nobody receives the messages left in the queue. an error does not stop releasing
+        // the rest, and the first one is returned
+        let mut result = Ok(());
+        while let Ok(message) = self.message_queue.dequeue() {
+            let r = message.free(allocator);
+            if result.is_ok() {
+                result = r;
+            }
+        }
+        let r = self.mapper.free_user_memory(allocator);
+        result.and(r).map_err(ProcessError::FailedToMap)
     }
 
-    pub fn enqueue_message(&mut self, id: Id, data: u32) -> Result<(), ProcessError> {
-        match self.message_queue.enqueue(Message { id, data }) {
+    pub fn enqueue_message(&mut self, message: Message) -> Result<(), ProcessError> {
+        match self.message_queue.enqueue(message) {
             Ok(()) => Ok(()),
             Err(bb::Error::Full) => Err(ProcessError::QueueIsFull),
             Err(bb::Error::Empty) => Err(ProcessError::QueueIsEmpty),
@@ -222,13 +439,55 @@ impl<'a> Process<'a> {
             Err(bb::Error::Empty) => Err(ProcessError::QueueIsEmpty),
         }
     }
+
+    // take the first message from `id`. the other messages are left in the queue
+    pub fn dequeue_message_from(&mut self, id: Id) -> Result<Message, ProcessError> {
+        match self.message_queue.dequeue_first(|message| message.id == id) {
+            Ok(x) => Ok(x),
+            Err(bb::Error::Full) => Err(ProcessError::QueueIsFull),
+            Err(bb::Error::Empty) => Err(ProcessError::QueueIsEmpty),
+        }
+    }
+
+    // the event which a message from `sender` causes, if the process is waiting for it
+    pub fn message_event(&self, sender: Id) -> Option<Event> {
+        match self.blocked_on {
+            Some(Event::Message) => Some(Event::Message),
+            Some(Event::MessageFrom(id)) if id == sender => Some(Event::MessageFrom(id)),
+            _ => None,
+        }
+    }
+
+    // the message which dequeue_message returns next
+    pub fn peek_message(&self) -> Result<Message, ProcessError> {
+        match self.message_queue.peek() {
+            Ok(x) => Ok(x),
+            Err(bb::Error::Full) => Err(ProcessError::QueueIsFull),
+            Err(bb::Error::Empty) => Err(ProcessError::QueueIsEmpty),
+        }
+    }
+
+    pub fn is_message_queue_full(&self) -> bool {
+        self.message_queue.is_full()
+    }
+
+    // fails with QueueIsFull if more messages than `capacity` are queued
+    pub fn set_queue_capacity(&mut self, capacity: usize) -> Result<(), ProcessError> {
+        match self.message_queue.set_capacity(capacity) {
+            Ok(()) => Ok(()),
+            Err(bb::Error::Full) => Err(ProcessError::QueueIsFull),
+            Err(bb::Error::Empty) => Err(ProcessError::QueueIsEmpty),
+        }
+    }
 }
 
 pub struct ProcessManager<'a> {
     procs: &'a mut [Process<'a>; N_PROCS],
     id_stack: [usize; N_PROCS],
     stack: usize,
-    sched_index: usize,
+    run_queue: RunQueue,
+    sleep_queue: SleepQueue,
+    wait_queue: WaitQueue,
 }
 
 impl<'a> ProcessManager<'a> {
@@ -238,12 +497,16 @@ impl<'a> ProcessManager<'a> {
         proc_tmp_pages: &'a mut [paging::PageTable; N_PROCS],
     ) -> ProcessManager<'a> {
         let mut id_stack = [0usize; N_PROCS];
+        let mut stack = 0;
         for (i, (p, t)) in proc_pages
             .iter_mut()
             .zip(proc_tmp_pages.iter_mut())
             .enumerate()
         {
-            id_stack[i] = i;
+            if i != SPARE_INDEX {
+                id_stack[stack] = i;
+                stack += 1;
+            }
             paging::PageTable::setup_tmp_table(p, t);
             procs[i].init(Id(i as u32), paging::Map::new(p, t));
             unsafe { procs[i].set_index(i) };
@@ -251,8 +514,10 @@ impl<'a> ProcessManager<'a> {
         ProcessManager {
             procs,
             id_stack,
-            stack: N_PROCS,
-            sched_index: 0,
+            stack,
+            run_queue: RunQueue::new(),
+            sleep_queue: SleepQueue::new(),
+            wait_queue: WaitQueue::new(),
         }
     }
 
@@ -276,34 +541,179 @@ impl<'a> ProcessManager<'a> {
         }
     }
 
-    pub fn dealloc(&mut self, proc: &Process) -> Result<(), ProcessError> {
-        if self.stack == N_PROCS {
-            Err(ProcessError::ProgramError("frame stack overflow"))
+    // the slot to build a new address space in, without taking a process slot. its user
+    // memory must be freed after use
+    pub fn spare(&mut self) -> &'a mut Process<'a> {
+        unsafe { &mut *((&mut self.procs[SPARE_INDEX]) as *mut Process<'a>) }
+    }
+
+    pub fn dealloc(&mut self, proc: &mut Process) -> Result<(), ProcessError> {
+        if let Status::Free = proc.status {
+            return Err(ProcessError::ProgramError("process is already freed"));
+        }
+        if self.stack == N_PROCS - 1 {
+            Err(ProcessError::ProgramError("process stack overflow"))
         } else {
+            self.run_queue.remove(proc.index);
+            self.sleep_queue.remove(proc.index);
+            self.wait_queue.remove(proc.index);
+            proc.clear();
             self.id_stack[self.stack] = proc.index;
             self.stack += 1;
             Ok(())
         }
     }
 
-    pub fn sched(&mut self) -> Option<*mut Process<'a>> {
-        let old_index = self.sched_index;
-        for i in old_index..N_PROCS {
-            match self.procs[i].status {
-                Status::Runnable => {
-                    self.sched_index = (i + 1) % N_PROCS;
-                    return Some((&mut self.procs[i]) as *mut Process<'a>);
-                }
+    // search a zombie child of `parent`. if `target` is given, only the child is checked.
+    // None means that there are children which are still alive.
+    pub fn find_zombie_child(
+        &mut self,
+        parent: Id,
+        target: Option<Id>,
+    ) -> Result<Option<&'a mut Process<'a>>, ProcessError> {
+        let mut found = false;
+        for i in 0..N_PROCS {
+            let p = unsafe { &mut *((&mut self.procs[i]) as *mut Process<'a>) };
+            if p.parent_id != parent || p.id == parent {
+                continue;
+            }
+            match target {
+                Some(id) if id != p.id => continue,
                 _ => (),
             }
+            match p.status {
+                Status::Free => (),
+                Status::Zonmbie => return Ok(Some(p)),
+                _ => found = true,
+            }
+        }
+        if found {
+            Ok(None)
+        } else {
+            Err(ProcessError::NoSuchProcess)
+        }
+    }
+
+    // called when the process `id` exits. zombie children are released, and the others
+    // are detached so that they are released on their exit.
+    pub fn release_children(&mut self, id: Id) -> Result<(), ProcessError> {
+        for i in 0..N_PROCS {
+            let p = unsafe { &mut *((&mut self.procs[i]) as *mut Process<'a>) };
+            if p.parent_id != id || p.id == id {
+                continue;
+            }
+            match p.status {
+                Status::Free => (),
+                Status::Zonmbie => self.dealloc(p)?,
+                _ => p.parent_id = p.id,
+            }
         }
-        for i in 0..old_index {
-            match self.procs[i].status {
-                Status::Runnable => {
-                    self.sched_index = (i + 1) % N_PROCS;
-                    return Some((&mut self.procs[i]) as *mut Process<'a>);
+        Ok(())
+    }
+
+    // make p runnable, and queue it behind the processes of the same priority
+    pub fn set_runnable(&mut self, p: &mut Process) {
+        p.status = Status::Runnable;
+        self.run_queue.push(p.index, p.priority as usize);
+    }
+
+    // make the process runnable again if it is waiting for `event`
+    pub fn wake_up(&mut self, p: &mut Process, event: Event) {
+        if p.blocked_on == Some(event) {
+            p.blocked_on = None;
+            self.sleep_queue.remove(p.index);
+            self.wait_queue.remove(p.index);
+            self.set_runnable(p);
+        }
+    }
+
+    // block p until `event` happens. if mtime reaches `deadline` first, the syscall of p
+    // fails with TimedOut
+    pub fn block(&mut self, p: &mut Process, event: Event, deadline: Option<u64>) {
+        p.block(event);
+        self.wait_queue.push(p.index, event);
+        if let Some(deadline) = deadline {
+            self.sleep_queue.insert(p.index, deadline);
+        }
+    }
+
+    // block p until mtime reaches `deadline`
+    pub fn sleep(&mut self, p: &mut Process, deadline: u64) {
+        self.block(p, Event::Timer, Some(deadline));
+    }
+
+    // make the process waiting for `event` runnable without queueing it, in order to switch
+    // to it right now. returns false if it is not waiting for the event
+    pub fn wake_up_directly(&mut self, p: &mut Process, event: Event) -> bool {
+        if p.blocked_on == Some(event) {
+            p.blocked_on = None;
+            self.sleep_queue.remove(p.index);
+            self.wait_queue.remove(p.index);
+            p.status = Status::Runnable;
+            true
+        } else {
+            false
+        }
+    }
+
+    // wake up the sleepers whose deadlines have passed. returns true if any is woken up
+    pub fn wake_up_sleepers(&mut self, now: u64) -> bool {
+        let mut woken = false;
+        while let Some(index) = self.sleep_queue.pop_expired(now) {
+            let p = unsafe { &mut *((&mut self.procs[index]) as *mut Process<'a>) };
+            match p.blocked_on {
+                Some(Event::Timer) => self.wake_up(p, Event::Timer),
+                Some(event) => {
+                    p.finish_syscall(SyscallError::TimedOut.to_syscall_result() as u32);
+                    self.wake_up(p, event);
                 }
-                _ => (),
+                None => continue,
+            }
+            woken = true;
+        }
+        woken
+    }
+
+    // the process which has waited for `event` longest. only the events which have a wait
+    // queue, that is, the ones which other processes cause, are found
+    pub fn find_blocked_on(&mut self, event: Event) -> Option<&'a mut Process<'a>> {
+        let index = self.wait_queue.first(event)?;
+        Some(unsafe { &mut *((&mut self.procs[index]) as *mut Process<'a>) })
+    }
+
+    // forget the grants to the process, since its id is reused by another process
+    pub fn revoke_grants_to(&mut self, id: Id) {
+        for p in self.procs.iter_mut() {
+            p.grants.revoke_all(id);
+        }
+    }
+
+    // make every process waiting for `event` runnable, in the order they started waiting
+    pub fn wake_up_all(&mut self, event: Event) {
+        while let Some(p) = self.find_blocked_on(event) {
+            self.wake_up(p, event);
+        }
+    }
+
+    // the earliest deadline of the sleepers
+    pub fn next_wakeup(&self) -> Option<u64> {
+        self.sleep_queue.first()
+    }
+
+    pub fn set_priority(&mut self, p: &mut Process, priority: u32) {
+        p.priority = priority;
+        if self.run_queue.contains(p.index) {
+            self.run_queue.remove(p.index);
+            self.run_queue.push(p.index, priority as usize);
+        }
+    }
+
+    // pick the runnable process with the smallest priority, in FIFO order among the same one
+    pub fn sched(&mut self) -> Option<*mut Process<'a>> {
+        while let Some(index) = self.run_queue.pop() {
+            // only runnable processes are queued, but check it just in case
+            if let Status::Runnable = self.procs[index].status {
+                return Some((&mut self.procs[index]) as *mut Process<'a>);
             }
         }
         None
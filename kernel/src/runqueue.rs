@@ -0,0 +1,73 @@
+use indexlist::{IndexList, List};
+use osmium_syscall::process::N_PRIORITIES;
+
+// FIFO queues of process indices, one for each priority.
+// a smaller priority is scheduled first.
+
+pub struct RunQueue {
+    links: IndexList,
+    queues: [List; N_PRIORITIES],
+    // bit i is set when the queue of priority i is not empty
+    nonempty: u32,
+}
+
+impl RunQueue {
+    pub fn new() -> RunQueue {
+        RunQueue {
+            links: IndexList::new(),
+            queues: [List::new(); N_PRIORITIES],
+            nonempty: 0,
+        }
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.links.contains(index)
+    }
+
+    // push the process at the tail. nothing happens if it is already queued
+    pub fn push(&mut self, index: usize, priority: usize) {
+        if self.contains(index) {
+            return;
+        }
+        self.links.push_back(&mut self.queues, priority, index);
+        self.nonempty |= 1 << priority;
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        let priority = match self.links.list_of(index) {
+            Some(priority) => priority,
+            None => return,
+        };
+        self.links.remove(&mut self.queues, index);
+        if self.queues[priority].is_empty() {
+            self.nonempty &= !(1 << priority);
+        }
+    }
+
+    // take the head of the non-empty queue with the smallest priority
+    pub fn pop(&mut self) -> Option<usize> {
+        if self.nonempty == 0 {
+            return None;
+        }
+        let index = self.queues[self.nonempty.trailing_zeros() as usize].first()?;
+        self.remove(index);
+        Some(index)
+    }
+}
+
+#[test]
+fn test_run_queue() {
+    let mut q = RunQueue::new();
+    assert_eq!(q.pop(), None);
+    q.push(3, 5);
+    q.push(1, 5);
+    q.push(2, 0);
+    // already queued with another priority
+    q.push(3, 0);
+    q.push(4, N_PRIORITIES - 1);
+    q.remove(2);
+    assert_eq!(q.pop(), Some(3));
+    assert_eq!(q.pop(), Some(1));
+    assert_eq!(q.pop(), Some(4));
+    assert_eq!(q.pop(), None);
+}
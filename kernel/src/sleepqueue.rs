@@ -0,0 +1,75 @@
+use indexlist::{IndexList, List};
+use proc::N_PROCS;
+
+// processes sleeping until their deadlines (in mtime clocks), sorted by the deadline.
+// processes with the same deadline are woken up in the order they fell asleep.
+
+pub struct SleepQueue {
+    links: IndexList,
+    queue: [List; 1],
+    deadline: [u64; N_PROCS],
+}
+
+impl SleepQueue {
+    pub fn new() -> SleepQueue {
+        SleepQueue {
+            links: IndexList::new(),
+            queue: [List::new()],
+            deadline: [0; N_PROCS],
+        }
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.links.contains(index)
+    }
+
+    // the earliest deadline
+    pub fn first(&self) -> Option<u64> {
+        self.queue[0].first().map(|index| self.deadline[index])
+    }
+
+    pub fn insert(&mut self, index: usize, deadline: u64) {
+        self.remove(index);
+        let mut before = self.queue[0].first();
+        while let Some(cur) = before {
+            if self.deadline[cur] > deadline {
+                break;
+            }
+            before = self.links.next(cur);
+        }
+        self.links.insert_before(&mut self.queue, 0, index, before);
+        self.deadline[index] = deadline;
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.links.remove(&mut self.queue, index);
+    }
+
+    // take the process whose deadline is not after `now`
+    pub fn pop_expired(&mut self, now: u64) -> Option<usize> {
+        match self.first() {
+            Some(deadline) if deadline <= now => self.links.pop_front(&mut self.queue, 0),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn test_sleep_queue() {
+    let mut q = SleepQueue::new();
+    assert_eq!(q.first(), None);
+    q.insert(1, 300);
+    q.insert(2, 100);
+    q.insert(3, 200);
+    q.insert(4, 100);
+    assert_eq!(q.first(), Some(100));
+    assert_eq!(q.pop_expired(99), None);
+    assert_eq!(q.pop_expired(200), Some(2));
+    assert_eq!(q.pop_expired(200), Some(4));
+    assert_eq!(q.pop_expired(200), Some(3));
+    assert_eq!(q.pop_expired(200), None);
+    // sleeping again moves the process
+    q.insert(1, 10);
+    assert_eq!(q.pop_expired(10), Some(1));
+    assert_eq!(q.first(), None);
+}
@@ -1,17 +1,29 @@
+use crate::args;
+use crate::csr;
+use crate::csr::satp;
+use crate::csr::{CSRRead, CSRWrite};
 use crate::elf;
+use crate::fd;
 use crate::files;
+use crate::grant;
 use crate::kernel;
-use crate::memlayout;
 use crate::paging;
+use crate::pipe;
 use crate::proc;
 use crate::trap;
 use crate::uart;
+use crate::usermem;
+use core::cmp;
 use core::convert;
+use core::mem;
 use core::slice;
-use core::str;
+use osmium_fs::{File, FileError};
 use osmium_syscall::errors::SyscallError;
+use osmium_syscall::file::Whence;
+use osmium_syscall::ipc;
 use osmium_syscall::number;
 use osmium_syscall::perm;
+use osmium_syscall::process::{ExitStatus, CURRENT_PROCESS, N_PRIORITIES, WAIT_ANY};
 
 #[derive(Copy, Clone, Debug)]
 pub enum Syscall {
@@ -41,9 +53,39 @@ pub enum Syscall {
     SendData {
         id: u32,
         data: u32,
+        timeout: u32,
     },
     ReceiveData {
         data_store: u32,
+        timeout: u32,
+    },
+    SendBuffer {
+        id: u32,
+        buf: u32,
+        len: u32,
+        timeout: u32,
+    },
+    ReceiveBuffer {
+        buf: u32,
+        size: u32,
+        len_store: u32,
+        timeout: u32,
+    },
+    SetQueueCapacity {
+        capacity: u32,
+    },
+    Call {
+        id: u32,
+        data: u32,
+    },
+    ReceiveFrom {
+        id: u32,
+        data_store: u32,
+        timeout: u32,
+    },
+    ReplyReceive {
+        reply: u32,
+        data_store: u32,
     },
     Map {
         src_id: u32,
@@ -61,6 +103,55 @@ pub enum Syscall {
         addr: u32,
         size: u32,
     },
+    Unmap {
+        addr: u32,
+        size: u32,
+    },
+    Open {
+        path: u32,
+        path_length: u32,
+    },
+    Close {
+        fd: u32,
+    },
+    Read {
+        fd: u32,
+        buf: u32,
+        size: u32,
+    },
+    Write {
+        fd: u32,
+        buf: u32,
+        size: u32,
+    },
+    Lseek {
+        fd: u32,
+        offset: u32,
+        whence: u32,
+    },
+    Wait {
+        id: u32,
+        status_store: u32,
+    },
+    SetPriority {
+        id: u32,
+        priority: u32,
+    },
+    Sleep {
+        usec: u32,
+    },
+    Pipe {
+        fds_store: u32,
+    },
+    Dup2 {
+        old_fd: u32,
+        new_fd: u32,
+    },
+    Grant {
+        peer: u32,
+        addr: u32,
+        perm: u32,
+    },
 }
 
 impl convert::From<proc::ProcessError> for SyscallError {
@@ -70,9 +161,9 @@ impl convert::From<proc::ProcessError> for SyscallError {
             proc::ProcessError::NoSuchProcess => SyscallError::NotFound,
             proc::ProcessError::QueueIsEmpty => SyscallError::QueueIsEmpty,
             proc::ProcessError::QueueIsFull => SyscallError::QueueIsFull,
-            proc::ProcessError::FailedToMap(_) | proc::ProcessError::ProgramError(_) => {
-                SyscallError::InternalError
-            }
+            proc::ProcessError::FailedToMap(e) => SyscallError::from(e),
+            proc::ProcessError::InvalidElf(e) => SyscallError::from(e),
+            proc::ProcessError::ProgramError(_) => SyscallError::InternalError,
         }
     }
 }
@@ -82,8 +173,8 @@ impl convert::From<paging::PageError> for SyscallError {
         match error {
             paging::PageError::FailedToAllocMemory => SyscallError::NoMemorySpace,
             paging::PageError::PageIsNotMapped => SyscallError::NotFound,
-            paging::PageError::IllegalAddress
-            | paging::PageError::MapError
+            paging::PageError::IllegalAddress => SyscallError::InvalidArguments,
+            paging::PageError::MapError
             | paging::PageError::AlreadyMapped
             | paging::PageError::ProgramError(_)
             | paging::PageError::NoMemory => SyscallError::InternalError,
@@ -91,6 +182,59 @@ impl convert::From<paging::PageError> for SyscallError {
     }
 }
 
+impl convert::From<fd::FdError> for SyscallError {
+    fn from(error: fd::FdError) -> Self {
+        match error {
+            fd::FdError::BadFileDescriptor => SyscallError::BadFileDescriptor,
+            fd::FdError::TooManyFiles => SyscallError::TooManyFiles,
+        }
+    }
+}
+
+impl convert::From<pipe::PipeError> for SyscallError {
+    fn from(error: pipe::PipeError) -> Self {
+        match error {
+            pipe::PipeError::TooManyPipes => SyscallError::TooManyFiles,
+            pipe::PipeError::Empty => SyscallError::QueueIsEmpty,
+            pipe::PipeError::Full => SyscallError::QueueIsFull,
+            pipe::PipeError::BrokenPipe => SyscallError::BrokenPipe,
+        }
+    }
+}
+
+impl convert::From<grant::GrantError> for SyscallError {
+    fn from(error: grant::GrantError) -> Self {
+        match error {
+            grant::GrantError::TooManyGrants => SyscallError::NoMemorySpace,
+        }
+    }
+}
+
+impl convert::From<elf::ElfError> for SyscallError {
+    fn from(error: elf::ElfError) -> Self {
+        if error.is_unsupported() {
+            SyscallError::UnsupportedExecutable
+        } else {
+            SyscallError::IllegalFile
+        }
+    }
+}
+
+fn from_file_error(error: FileError) -> SyscallError {
+    match error {
+        FileError::NotFound => SyscallError::NotFound,
+        FileError::IllegalPath | FileError::InvalidOffset | FileError::AlreadyExists => {
+            SyscallError::InvalidArguments
+        }
+        FileError::IllegalType | FileError::TooLarge => SyscallError::IllegalFile,
+        FileError::NoSpace => SyscallError::NoMemorySpace,
+        FileError::EndOfFile
+        | FileError::InternalError
+        | FileError::BrokenFile
+        | FileError::BrokenFileSystem => SyscallError::InternalError,
+    }
+}
+
 impl Syscall {
     pub fn from_trap_frame(tf: &trap::TrapFrame) -> Result<Syscall, SyscallError> {
         dprintln!("syscall number: {:x}", tf.regs.a0());
@@ -119,9 +263,11 @@ impl Syscall {
             number::SYS_SEND => Ok(Syscall::SendData {
                 id: tf.regs.a1(),
                 data: tf.regs.a2(),
+                timeout: tf.regs.a3(),
             }),
             number::SYS_RECEIVE => Ok(Syscall::ReceiveData {
                 data_store: tf.regs.a1(),
+                timeout: tf.regs.a2(),
             }),
             number::SYS_MMAP => Ok(Syscall::Map {
                 src_id: tf.regs.a1(),
@@ -139,35 +285,128 @@ impl Syscall {
                 addr: tf.regs.a1(),
                 size: tf.regs.a2(),
             }),
+            number::SYS_UNMAP => Ok(Syscall::Unmap {
+                addr: tf.regs.a1(),
+                size: tf.regs.a2(),
+            }),
+            number::SYS_OPEN => Ok(Syscall::Open {
+                path: tf.regs.a1(),
+                path_length: tf.regs.a2(),
+            }),
+            number::SYS_CLOSE => Ok(Syscall::Close { fd: tf.regs.a1() }),
+            number::SYS_READ => Ok(Syscall::Read {
+                fd: tf.regs.a1(),
+                buf: tf.regs.a2(),
+                size: tf.regs.a3(),
+            }),
+            number::SYS_WRITE => Ok(Syscall::Write {
+                fd: tf.regs.a1(),
+                buf: tf.regs.a2(),
+                size: tf.regs.a3(),
+            }),
+            number::SYS_LSEEK => Ok(Syscall::Lseek {
+                fd: tf.regs.a1(),
+                offset: tf.regs.a2(),
+                whence: tf.regs.a3(),
+            }),
+            number::SYS_WAIT => Ok(Syscall::Wait {
+                id: tf.regs.a1(),
+                status_store: tf.regs.a2(),
+            }),
+            number::SYS_SET_PRIORITY => Ok(Syscall::SetPriority {
+                id: tf.regs.a1(),
+                priority: tf.regs.a2(),
+            }),
+            number::SYS_SLEEP => Ok(Syscall::Sleep { usec: tf.regs.a1() }),
+            number::SYS_SEND_BUF => Ok(Syscall::SendBuffer {
+                id: tf.regs.a1(),
+                buf: tf.regs.a2(),
+                len: tf.regs.a3(),
+                timeout: tf.regs.a4(),
+            }),
+            number::SYS_RECEIVE_BUF => Ok(Syscall::ReceiveBuffer {
+                buf: tf.regs.a1(),
+                size: tf.regs.a2(),
+                len_store: tf.regs.a3(),
+                timeout: tf.regs.a4(),
+            }),
+            number::SYS_SET_QUEUE_CAPACITY => Ok(Syscall::SetQueueCapacity {
+                capacity: tf.regs.a1(),
+            }),
+            number::SYS_CALL => Ok(Syscall::Call {
+                id: tf.regs.a1(),
+                data: tf.regs.a2(),
+            }),
+            number::SYS_REPLY_RECV => Ok(Syscall::ReplyReceive {
+                reply: tf.regs.a1(),
+                data_store: tf.regs.a2(),
+            }),
+            number::SYS_RECEIVE_FROM => Ok(Syscall::ReceiveFrom {
+                id: tf.regs.a1(),
+                data_store: tf.regs.a2(),
+                timeout: tf.regs.a3(),
+            }),
+            number::SYS_PIPE => Ok(Syscall::Pipe {
+                fds_store: tf.regs.a1(),
+            }),
+            number::SYS_DUP2 => Ok(Syscall::Dup2 {
+                old_fd: tf.regs.a1(),
+                new_fd: tf.regs.a2(),
+            }),
+            number::SYS_GRANT => Ok(Syscall::Grant {
+                peer: tf.regs.a1(),
+                addr: tf.regs.a2(),
+                perm: tf.regs.a3(),
+            }),
             _ => Err(SyscallError::InvalidSyscallNumber),
         }
     }
 }
 
-pub fn uart_read(buf: u32, size: u32) -> Result<u32, SyscallError> {
-    // TODO: check buf's validity
-    let buf: &mut [u8] = unsafe { slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+fn read_uart(buf: &mut [u8]) -> u32 {
     for c in buf.iter_mut() {
         *c = uart::read_byte();
     }
-    Ok(size)
+    buf.len() as u32
 }
 
-pub fn uart_write(buf: u32, size: u32) -> Result<u32, SyscallError> {
-    // TODO: check buf's validity
-    let buf: &mut [u8] = unsafe { slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+fn write_uart(buf: &[u8]) -> u32 {
     for c in buf {
         print!("{}", *c as char);
     }
-    Ok(size)
+    buf.len() as u32
+}
+
+pub fn uart_read(buf: u32, size: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    let p = k.current_process.as_mut().unwrap();
+    let buf = usermem::user_slice_mut(&mut p.mapper, buf, size, &mut k.allocator)?;
+    Ok(read_uart(buf))
+}
+
+pub fn uart_write(buf: u32, size: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    let p = k.current_process.as_ref().unwrap();
+    let buf = usermem::user_slice(&p.mapper, buf, size)?;
+    Ok(write_uart(buf))
+}
+
+// copy a path from the user memory. the length of the path is returned together
+fn copy_path_from_user(
+    addr: u32,
+    length: u32,
+    k: &kernel::Kernel,
+) -> Result<([u8; files::MAX_PATH_LENGTH], usize), SyscallError> {
+    let length = length as usize;
+    if length >= files::MAX_PATH_LENGTH {
+        return Err(SyscallError::InvalidArguments);
+    }
+    let mut path = [0u8; files::MAX_PATH_LENGTH];
+    let p = k.current_process.as_ref().unwrap();
+    usermem::copy_from_user(&p.mapper, &mut path[..length], addr)?;
+    Ok((path, length))
 }
 
 pub fn exit(status: u32, kernel: &mut kernel::Kernel) -> Result<u32, SyscallError> {
-    match kernel.current_process {
-        Some(ref mut p) => p.exit(status),
-        None => (),
-    };
-    kernel.current_process = None;
+    kernel.exit_current_process(ExitStatus::exited(status))?;
     Ok(0)
 }
 
@@ -178,9 +417,25 @@ pub fn get_proc_id(k: &kernel::Kernel) -> Result<u32, SyscallError> {
     }
 }
 
-pub fn yield_process(k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
-    k.current_process.as_mut().unwrap().status = proc::Status::Runnable;
-    k.current_process = None;
+pub fn yield_process(tf: &trap::TrapFrame, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    // the process is no longer current when the result is set, so set it here
+    let mut new_tf = tf.clone();
+    new_tf.regs.set_syscall_result(0);
+    k.preempt_current_process(new_tf);
+    Ok(0)
+}
+
+// block the caller for `usec` microseconds
+fn sleep(usec: u32, tf: &trap::TrapFrame, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    let deadline =
+        csr::timer::now() + csr::timer::ms2clk(csr::timer::MicroSeccond::new(usec as u64));
+    // the syscall has finished when the process is woken up
+    let p = k.current_process.take().unwrap();
+    let mut new_tf = tf.clone();
+    new_tf.regs.set_syscall_result(0);
+    p.trap_frame = new_tf;
+    k.process_manager.sleep(p, deadline);
+    k.set_timer();
     Ok(0)
 }
 
@@ -199,9 +454,14 @@ pub fn fork(k: &mut kernel::Kernel, tf: &trap::TrapFrame) -> Result<u32, Syscall
             });
         }
     };
+    // mark as used until it gets runnable
+    process.status = proc::Status::NotRunnable;
     match process.create(&mut k.mapper) {
         Ok(()) => (),
-        Err(e) => return Err(SyscallError::NoMemorySpace),
+        Err(e) => {
+            k.process_manager.dealloc(process)?;
+            return Err(SyscallError::NoMemorySpace);
+        }
     };
     // setup CoW and dup page table
     match k
@@ -214,17 +474,25 @@ pub fn fork(k: &mut kernel::Kernel, tf: &trap::TrapFrame) -> Result<u32, Syscall
         Ok(()) => (),
         Err(e) => {
             dprintln!("failed to create cow: {}", e);
+            process.mapper.free_user_memory(&mut k.allocator)?;
+            k.process_manager.dealloc(process)?;
             return Err(SyscallError::InternalError);
         }
     }
 
-    // change status
-    process.status = proc::Status::Runnable;
+    // inherit opened files
+    process.fds = k.current_process.as_ref().unwrap().fds;
+    for desc in process.fds.iter() {
+        k.dup_descriptor(desc);
+    }
+
     // set child's tf. and
     let mut new_tf = tf.clone();
     new_tf.regs.set_syscall_result(0);
     process.trap_frame = new_tf;
     process.parent_id = k.current_process.as_ref().unwrap().id;
+    process.priority = k.current_process.as_ref().unwrap().priority;
+    k.process_manager.set_runnable(process);
     // parent's retval is child's proc id
     Ok(process.id.to_u32())
 }
@@ -237,44 +505,68 @@ fn execve(
     tf: &mut trap::TrapFrame,
     k: &mut kernel::Kernel,
 ) -> Result<u32, SyscallError> {
-    let name: &str;
-    unsafe {
-        // TODO: user memcheck
-        match str::from_utf8(slice::from_raw_parts(
-            filename as *const u8,
-            filename_length as usize,
-        )) {
-            Ok(f) => name = f,
-            Err(_) => {
-                return Err(SyscallError::InvalidArguments);
-            }
-        };
-    }
+    let (name, length) = copy_path_from_user(filename, filename_length, k)?;
+    // copy the arguments before the address space is replaced
+    let mut args = args::Args::new();
+    args.copy_from_user(&k.current_process.as_ref().unwrap().mapper, argv, envp)?;
 
-    let file = match files::search(name) {
-        Some(file) => file,
-        None => return Err(SyscallError::NotFound),
+    let mut file = match files::search(&mut k.filesystem, &name[..length]) {
+        Ok(file) => file,
+        Err(e) => return Err(from_file_error(e)),
+    };
+    let bytes = match files::read_all(&mut k.filesystem, &mut file) {
+        Ok(bytes) => bytes,
+        Err(e) => return Err(from_file_error(e)),
     };
 
-    let e = match elf::Elf::new(file.bytes) {
+    let e = match elf::Elf::new(bytes) {
         Ok(e) => e,
-        Err(_) => return Err(SyscallError::IllegalFile),
+        Err(e) => {
+            dprintln!("execve: {}", e);
+            return Err(SyscallError::from(e));
+        }
     };
-    match k
-        .current_process
-        .as_mut()
-        .unwrap()
-        .load_elf(&e, &mut k.allocator)
-    {
-        Ok(()) => (),
-        Err(e) => return Err(SyscallError::IllegalFile),
+
+    // the new image is built in the address space of the spare slot, so that the caller
+    // is left intact when loading fails
+    let spare = k.process_manager.spare();
+    k.mapper.clone_dir(&mut spare.mapper);
+    let sp = match load_image(spare, &e, &args, &mut k.allocator) {
+        Ok(sp) => sp,
+        Err(err) => {
+            spare.mapper.free_user_memory(&mut k.allocator)?;
+            return Err(err);
+        }
     };
-    dprintln!("set entry point: {:x}", e.elf.entry);
-    let new_tf = trap::TrapFrame::new(e.elf.entry, memlayout::USER_STACK_BOTTOMN);
+
+    // switch to the new address space, and release the old one
+    let p = k.current_process.as_mut().unwrap();
+    mem::swap(&mut p.mapper, &mut spare.mapper);
+    // the granted pages are gone with the old address space
+    p.grants = grant::GrantTable::new();
+    spare.mapper.free_user_memory(&mut k.allocator)?;
+
+    dprintln!("set entry point: {:x}", e.entry());
+    let new_tf = trap::TrapFrame::new(e.entry(), sp);
     *tf = new_tf;
     Ok(0)
 }
 
+// load the ELF and arguments into the empty address space of p. returns the initial sp
+fn load_image(
+    p: &mut proc::Process,
+    e: &elf::Elf,
+    args: &args::Args,
+    allocator: &mut paging::Allocator,
+) -> Result<u32, SyscallError> {
+    p.load_elf(e, allocator)?;
+    let sp;
+    address_space!(p, {
+        sp = args.push_to_user_stack(&mut p.mapper, allocator);
+    });
+    sp
+}
+
 fn check_process_status(id: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
     let p: &mut proc::Process = k.process_manager.id2proc(proc::Id(id))?;
     if p.parent_id != k.current_process.as_ref().unwrap().id {
@@ -283,34 +575,525 @@ fn check_process_status(id: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallE
     Ok(p.status.to_u32())
 }
 
-fn send_data(id: u32, data: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+fn wait(
+    id: u32,
+    status_store: u32,
+    tf: &mut trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    let target = if id == WAIT_ANY {
+        None
+    } else {
+        Some(proc::Id(id))
+    };
+    let my_id = k.current_process.as_ref().unwrap().id;
+    match k.process_manager.find_zombie_child(my_id, target)? {
+        Some(child) => {
+            if status_store != 0 {
+                let p = k.current_process.as_mut().unwrap();
+                usermem::write_to_user(
+                    &mut p.mapper,
+                    status_store,
+                    &child.exit_status,
+                    &mut k.allocator,
+                )?;
+            }
+            let child_id = child.id;
+            k.process_manager.dealloc(child)?;
+            Ok(child_id.to_u32())
+        }
+        None => {
+            // sleep until a child exits. this syscall is issued again after woken up
+            let p = k.current_process.take().unwrap();
+            let mut new_tf = tf.clone();
+            new_tf.pc -= 4;
+            p.trap_frame = new_tf;
+            p.block(proc::Event::ChildExit);
+            Ok(0)
+        }
+    }
+}
+
+// change the priority of the caller or its child, and return the old one.
+// a process cannot get a higher priority (smaller value) than the caller's.
+fn set_priority(id: u32, priority: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    if priority as usize >= N_PRIORITIES {
+        return Err(SyscallError::InvalidArguments);
+    }
+    let current = k.current_process.as_mut().unwrap();
+    if priority < current.priority {
+        return Err(SyscallError::PermissionDenied);
+    }
+    if id == CURRENT_PROCESS || id == current.id.to_u32() {
+        // the current process is not in the run queue
+        let old = current.priority;
+        current.priority = priority;
+        return Ok(old);
+    }
     let p = k.process_manager.id2proc(proc::Id(id))?;
+    if let proc::Status::Free = p.status {
+        return Err(SyscallError::NotFound);
+    }
+    if !p.has_parent() || p.parent_id != current.id {
+        return Err(SyscallError::PermissionDenied);
+    }
+    let old = p.priority;
+    k.process_manager.set_priority(p, priority);
+    Ok(old)
+}
+
+// block the current process on `event` for at most `timeout` microseconds.
+// the syscall is issued again when the process is woken up by the event
+fn block_current_process(
+    event: proc::Event,
+    timeout: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) {
+    let deadline = if timeout == ipc::WAIT_FOREVER {
+        None
+    } else {
+        Some(csr::timer::now() + csr::timer::ms2clk(csr::timer::MicroSeccond::new(timeout as u64)))
+    };
+    let p = k.current_process.take().unwrap();
+    let mut new_tf = tf.clone();
+    new_tf.pc -= 4;
+    p.trap_frame = new_tf;
+    k.process_manager.block(p, event, deadline);
+    k.set_timer();
+}
+
+// copy [ptr, ptr + len) of the address space in satp to a message in a kernel frame.
+// each buffered message holds a whole frame until it is received, so a queue pins at most
+// MAX_QUEUE_CAPACITY frames (128 KiB)
+fn buffer_message(
+    id: proc::Id,
+    map: &paging::Map,
+    ptr: u32,
+    len: u32,
+    allocator: &mut paging::Allocator,
+) -> Result<proc::Message, SyscallError> {
+    if len as usize > ipc::MAX_MESSAGE_LENGTH {
+        return Err(SyscallError::InvalidArguments);
+    }
+    let src = usermem::user_slice(map, ptr, len)?;
+    let frame = allocator.alloc()?;
+    let dst = unsafe {
+        slice::from_raw_parts_mut(
+            frame.phys_addr().kern_virt_addr().as_mut_ptr::<u8>(),
+            len as usize,
+        )
+    };
+    dst.copy_from_slice(src);
+    Ok(proc::Message {
+        id,
+        body: proc::Body::Buffer(frame, len),
+    })
+}
+
+// put `message` in the queue of `id`. the message is released unless it is queued
+fn send_message(
+    id: u32,
+    message: proc::Message,
+    timeout: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    let error = match k.process_manager.id2proc(proc::Id(id)) {
+        Err(e) => SyscallError::from(e),
+        Ok(p) => match p.status {
+            proc::Status::Free | proc::Status::Zonmbie => SyscallError::NotFound,
+            _ => match p.enqueue_message(message) {
+                Ok(()) => {
+                    if let Some(event) = p.message_event(message.id) {
+                        k.process_manager.wake_up(p, event);
+                    }
+                    return Ok(0);
+                }
+                // nobody else makes a space if the process sends to itself
+                Err(proc::ProcessError::QueueIsFull)
+                    if timeout != ipc::NO_WAIT && p.id != message.id =>
+                {
+                    message.free(&mut k.allocator)?;
+                    // the receiver takes the message from this process when its queue gets a
+                    // space (see accept_blocked_sender)
+                    block_current_process(proc::Event::QueueSpace(p.id), timeout, tf, k);
+                    // or right now if it waits only for this process (see take_blocked_message)
+                    k.process_manager
+                        .wake_up(p, proc::Event::MessageFrom(message.id));
+                    return Ok(0);
+                }
+                Err(e) => SyscallError::from(e),
+            },
+        },
+    };
+    message.free(&mut k.allocator)?;
+    Err(error)
+}
+
+fn send_data(
+    id: u32,
+    data: u32,
+    timeout: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
     let my_id = k.current_process.as_ref().unwrap().id;
-    match p.enqueue_message(my_id, data) {
-        Ok(()) => Ok(0),
-        Err(proc::ProcessError::QueueIsFull) => Err(SyscallError::QueueIsFull),
-        Err(_) => Err(SyscallError::InternalError),
+    send_message(id, proc::Message::word(my_id, data), timeout, tf, k)
+}
+
+fn send_buffer(
+    id: u32,
+    ptr: u32,
+    len: u32,
+    timeout: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    let message = {
+        let p = k.current_process.as_ref().unwrap();
+        buffer_message(p.id, &p.mapper, ptr, len, &mut k.allocator)?
+    };
+    send_message(id, message, timeout, tf, k)
+}
+
+// the message of the sender blocked in SYS_SEND or SYS_SEND_BUF for a space of the queue
+fn blocked_message(
+    sender: &proc::Process,
+    allocator: &mut paging::Allocator,
+) -> Result<proc::Message, SyscallError> {
+    // the syscall issued by the sender
+    let regs = sender.trap_frame.regs;
+    if regs.a0() == number::SYS_SEND {
+        Ok(proc::Message::word(sender.id, regs.a2()))
+    } else {
+        // the buffer is in the address space of the sender
+        let old_satp = satp::SATP::read();
+        satp::SATP::set_ppn(sender.ppn());
+        let message = buffer_message(sender.id, &sender.mapper, regs.a2(), regs.a3(), allocator);
+        old_satp.commit();
+        message
     }
 }
 
-fn receive_data(ptr: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
-    let data_store: Option<&mut u32> = if ptr == 0 {
+// take the message of the sender blocked for a space of the queue of `receiver`, and let
+// the sender go on. a caller of SYS_CALL waits for the reply after that
+fn accept_sender(
+    receiver: proc::Id,
+    sender: &mut proc::Process,
+    pm: &mut proc::ProcessManager,
+    allocator: &mut paging::Allocator,
+) -> Result<proc::Message, SyscallError> {
+    let event = proc::Event::QueueSpace(receiver);
+    let regs = sender.trap_frame.regs;
+    if regs.a0() == number::SYS_CALL {
+        // the receiver sets the result when it replies
+        sender.trap_frame.pc += 4;
+        pm.wake_up_directly(sender, event);
+        pm.block(sender, proc::Event::Reply(receiver), None);
+        return Ok(proc::Message {
+            id: sender.id,
+            body: proc::Body::Call(regs.a2()),
+        });
+    }
+    let message = blocked_message(sender, allocator);
+    sender.finish_syscall(match message {
+        Ok(_) => 0,
+        Err(e) => e.to_syscall_result() as u32,
+    });
+    pm.wake_up(sender, event);
+    message
+}
+
+// messages are taken out of the queue of p. the messages of the processes blocked in
+// SYS_SEND, SYS_SEND_BUF or SYS_CALL are put in the space
+fn accept_blocked_sender(
+    p: &mut proc::Process,
+    pm: &mut proc::ProcessManager,
+    allocator: &mut paging::Allocator,
+) {
+    let event = proc::Event::QueueSpace(p.id);
+    while !p.is_message_queue_full() {
+        let sender = match pm.find_blocked_on(event) {
+            Some(sender) => sender,
+            None => return,
+        };
+        if let Ok(message) = accept_sender(p.id, sender, pm, allocator) {
+            // never fails, since the queue has a space
+            if p.enqueue_message(message).is_err() {
+                let _ = message.free(allocator);
+            }
+        }
+    }
+}
+
+// take the message of `from` directly if it is blocked for a space of the queue of the
+// current process. the queue may be full of the messages of others
+fn take_blocked_message(from: proc::Id, k: &mut kernel::Kernel) -> Option<proc::Message> {
+    let my_id = k.current_process_id().unwrap();
+    let event = proc::Event::QueueSpace(my_id);
+    let sender = k
+        .process_manager
+        .id2proc(from)
+        .ok()
+        .filter(|sender| sender.blocked_on == Some(event))?;
+    accept_sender(my_id, sender, &mut k.process_manager, &mut k.allocator).ok()
+}
+
+// the server does not reply to `caller`, e.g. because it has received another call
+fn abandon_caller(caller: proc::Id, server: proc::Id, k: &mut kernel::Kernel) {
+    let event = proc::Event::Reply(server);
+    let caller = k
+        .process_manager
+        .id2proc(caller)
+        .ok()
+        .filter(|caller| caller.blocked_on == Some(event));
+    if let Some(caller) = caller {
+        let error = SyscallError::NotFound.to_syscall_result() as u32;
+        caller.trap_frame.regs.set_syscall_result(error);
+        k.process_manager.wake_up(caller, event);
+    }
+}
+
+// take the next message (from `from` if given) out of the queue of the current process
+fn take_message(from: Option<proc::Id>, k: &mut kernel::Kernel) -> Option<proc::Message> {
+    let message = {
+        let p = k.current_process.as_mut().unwrap();
+        let message = match from {
+            Some(id) => p.dequeue_message_from(id).ok(),
+            None => p.dequeue_message().ok(),
+        };
+        if message.is_some() {
+            accept_blocked_sender(p, &mut k.process_manager, &mut k.allocator);
+        }
+        message
+    };
+    let message = match (message, from) {
+        (Some(message), _) => message,
+        (None, Some(id)) => take_blocked_message(id, k)?,
+        (None, None) => return None,
+    };
+    if let proc::Body::Call(_) = message.body {
+        // only one caller is replied to. the one which has not been replied gives up
+        let (my_id, displaced) = {
+            let p = k.current_process.as_mut().unwrap();
+            (p.id, p.last_caller.replace(message.id))
+        };
+        if let Some(caller) = displaced {
+            abandon_caller(caller, my_id, k);
+        }
+    }
+    Some(message)
+}
+
+fn wait_for_message(
+    from: Option<proc::Id>,
+    timeout: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    // nobody sends the message if `from` has gone. see also Kernel::exit_current_process
+    if let Some(id) = from {
+        let alive = match k.process_manager.id2proc(id) {
+            Ok(p) => match p.status {
+                proc::Status::Free | proc::Status::Zonmbie => false,
+                _ => true,
+            },
+            Err(_) => false,
+        };
+        if !alive {
+            return Err(SyscallError::NotFound);
+        }
+    }
+    if timeout == ipc::NO_WAIT {
+        return Err(SyscallError::QueueIsEmpty);
+    }
+    let event = match from {
+        Some(id) => proc::Event::MessageFrom(id),
+        None => proc::Event::Message,
+    };
+    block_current_process(event, timeout, tf, k);
+    Ok(0)
+}
+
+// receive a message (from `from` if given) as a word. only the first 4 bytes of a buffer
+// are stored
+fn receive_data(
+    from: Option<proc::Id>,
+    ptr: u32,
+    timeout: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    // check the store before taking the message out of the queue
+    let data_store: Option<&mut [u8]> = if ptr == 0 {
         None
     } else {
-        // TODO: check access validity
-        Some(unsafe { &mut *(ptr as *mut u32) })
+        let p = k.current_process.as_mut().unwrap();
+        let size = mem::size_of::<u32>() as u32;
+        Some(usermem::user_slice_mut(
+            &mut p.mapper,
+            ptr,
+            size,
+            &mut k.allocator,
+        )?)
+    };
+    match take_message(from, k) {
+        Some(message) => {
+            if let Some(store) = data_store {
+                for x in store.iter_mut() {
+                    *x = 0;
+                }
+                message.copy_to(store);
+            }
+            message.free(&mut k.allocator)?;
+            Ok(message.id.to_u32())
+        }
+        None => wait_for_message(from, timeout, tf, k),
+    }
+}
+
+// receive a message into [ptr, ptr + size), and store its length to `len_store`.
+// the message is left in the queue if it is longer than the buffer, and its length is
+// stored so that the caller can retry with a large enough buffer
+fn receive_buffer(
+    ptr: u32,
+    size: u32,
+    len_store: u32,
+    timeout: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    let (buf, len_store) = {
+        let p = k.current_process.as_mut().unwrap();
+        let buf = usermem::user_slice_mut(&mut p.mapper, ptr, size, &mut k.allocator)?;
+        let len_store: Option<&mut [u8]> = if len_store == 0 {
+            None
+        } else {
+            let size = mem::size_of::<u32>() as u32;
+            Some(usermem::user_slice_mut(
+                &mut p.mapper,
+                len_store,
+                size,
+                &mut k.allocator,
+            )?)
+        };
+        match p.peek_message() {
+            Ok(ref message) if message.len() > size => {
+                if let Some(store) = len_store {
+                    store.copy_from_slice(&message.len().to_ne_bytes());
+                }
+                return Err(SyscallError::InvalidArguments);
+            }
+            _ => (),
+        }
+        (buf, len_store)
+    };
+    match take_message(None, k) {
+        Some(message) => {
+            message.copy_to(buf);
+            if let Some(store) = len_store {
+                store.copy_from_slice(&message.len().to_ne_bytes());
+            }
+            message.free(&mut k.allocator)?;
+            Ok(message.id.to_u32())
+        }
+        None => wait_for_message(None, timeout, tf, k),
+    }
+}
+
+// send `data` to `id` and wait for the reply, which is returned in a1.
+// if `id` is waiting for a message, it runs right away
+fn call(
+    id: u32,
+    data: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    let server = k.process_manager.id2proc(proc::Id(id))?;
+    let my_id = k.current_process_id().unwrap();
+    if server.id == my_id {
+        return Err(SyscallError::InvalidArguments);
+    }
+    match server.status {
+        proc::Status::Free | proc::Status::Zonmbie => return Err(SyscallError::NotFound),
+        _ => (),
+    }
+    let message = proc::Message {
+        id: my_id,
+        body: proc::Body::Call(data),
     };
-    match k.current_process.as_mut().unwrap().dequeue_message() {
-        Ok(proc::Message { id, data }) => {
-            match data_store {
-                Some(store) => *store = data,
-                None => (),
-            };
-            Ok(id.to_u32())
+    match server.enqueue_message(message) {
+        Ok(()) => (),
+        // wait for a space like SYS_SEND. the call is taken by accept_sender then
+        Err(proc::ProcessError::QueueIsFull) => {
+            block_current_process(proc::Event::QueueSpace(server.id), ipc::WAIT_FOREVER, tf, k);
+            k.process_manager
+                .wake_up(server, proc::Event::MessageFrom(my_id));
+            return Ok(0);
+        }
+        Err(e) => return Err(SyscallError::from(e)),
+    }
+
+    // the server sets the result when it replies
+    let p = k.current_process.take().unwrap();
+    p.trap_frame = tf.clone();
+    k.process_manager
+        .block(p, proc::Event::Reply(server.id), None);
+    // the server may be waiting for any message, or for a message from this process
+    if let Some(event) = server.message_event(my_id) {
+        if k.process_manager.wake_up_directly(server, event) {
+            k.current_process = Some(server);
+        }
+    }
+    Ok(0)
+}
+
+// reply `reply` to the last caller, and wait for the next message like SYS_RECEIVE.
+// if no message has come, the caller runs right away
+fn reply_receive(
+    reply: u32,
+    data_store: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    let (my_id, last_caller) = {
+        let p = k.current_process.as_mut().unwrap();
+        (p.id, p.last_caller.take())
+    };
+    let event = proc::Event::Reply(my_id);
+    // the caller may have gone
+    let caller = last_caller
+        .and_then(|id| k.process_manager.id2proc(id).ok())
+        .filter(|caller| caller.blocked_on == Some(event))
+        .map(|caller| {
+            caller.trap_frame.regs.set_syscall_result(0);
+            caller.trap_frame.regs.set_a1(reply);
+            caller
+        });
+
+    // this is issued again after the server is woken up, but the reply is not sent twice
+    // since last_caller has been taken
+    let result = receive_data(None, data_store, ipc::WAIT_FOREVER, tf, k);
+    if let Some(caller) = caller {
+        if k.current_process.is_none() && k.process_manager.wake_up_directly(caller, event) {
+            k.current_process = Some(caller);
+        } else {
+            k.process_manager.wake_up(caller, event);
         }
-        Err(proc::ProcessError::QueueIsEmpty) => Err(SyscallError::QueueIsEmpty),
-        Err(_) => Err(SyscallError::InternalError),
     }
+    result
+}
+
+fn set_queue_capacity(capacity: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    if capacity == 0 || capacity as usize > ipc::MAX_QUEUE_CAPACITY {
+        return Err(SyscallError::InvalidArguments);
+    }
+    let p = k.current_process.as_mut().unwrap();
+    p.set_queue_capacity(capacity as usize)?;
+    // the senders waiting for a space
+    accept_blocked_sender(p, &mut k.process_manager, &mut k.allocator);
+    Ok(0)
 }
 
 impl convert::From<perm::Perm> for paging::Flag {
@@ -352,10 +1135,6 @@ fn mmap(
 
     let flag = paging::Flag::from(p);
 
-    if !src_p.mapper.check_perm(src_addr, flag) {
-        return Err(SyscallError::PermissionDenied);
-    }
-
     if !src_addr.is_page_aligned() {
         return Err(SyscallError::InvalidAlignment);
     }
@@ -364,25 +1143,82 @@ fn mmap(
         return Err(SyscallError::InvalidAlignment);
     }
 
-    let frame: paging::Frame;
+    // only the peer maps the page into its own address space, and the owner must have
+    // granted it
+    let caller = k.current_process.as_ref().unwrap().id;
+    if caller != dst_p.id || !src_p.grants.is_granted(dst_p.id, src_addr.to_u32(), p) {
+        return Err(SyscallError::PermissionDenied);
+    }
 
+    // dst is the caller, so its page table is in satp. never replace its own pages
+    let dst_page = paging::Page::from_addr(dst_addr);
+    if dst_addr.to_u32() < paging::USER_MEMORY_BASE as u32
+        || dst_addr.to_u32() >= paging::PAGE_TABLE_WINDOW as u32
+        || dst_p.mapper.is_mapped(dst_page)
+    {
+        return Err(SyscallError::InvalidArguments);
+    }
+
+    let frame;
     address_space!(src_p, {
-        let src_page = paging::Page::from_addr(src_addr);
-        frame = src_p.mapper.frame(src_page)?;
+        frame = if src_p.mapper.check_perm(src_addr, flag) {
+            src_p
+                .mapper
+                .frame(paging::Page::from_addr(src_addr))
+                .map_err(SyscallError::from)
+        } else {
+            Err(SyscallError::PermissionDenied)
+        };
     });
 
-    address_space!(dst_p, {
-        let dst_page = paging::Page::from_addr(dst_addr);
-        dst_p.mapper.map(
-            dst_page,
-            frame,
-            flag | paging::Flag::VALID | paging::Flag::USER,
-            &mut k.allocator,
-        )?;
-    });
+    dst_p.mapper.map(
+        dst_page,
+        frame?,
+        flag | paging::Flag::VALID | paging::Flag::USER,
+        &mut k.allocator,
+    )?;
+    Ok(0)
+}
+
+// allow `peer` to map the page of the caller at `addr` by SYS_MMAP, with the permissions
+// in perm_bits. the empty permission revokes the grant. mappings already made are kept
+fn grant(
+    peer: u32,
+    addr: u32,
+    perm_bits: u32,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    let perm = match perm::Perm::from_bits(perm_bits) {
+        Some(perm) => perm,
+        None => return Err(SyscallError::InvalidArguments),
+    };
+    if !paging::VirtAddr::new(addr).is_page_aligned() {
+        return Err(SyscallError::InvalidAlignment);
+    }
+    let peer = k.process_manager.id2proc(proc::Id(peer))?;
+    match peer.status {
+        proc::Status::Free | proc::Status::Zonmbie => return Err(SyscallError::NotFound),
+        _ => (),
+    }
+    let p = k.current_process.as_mut().unwrap();
+    p.grants.grant(peer.id, addr, perm)?;
     Ok(0)
 }
 
+// [addr, addr + size) must be in the user memory, below the page table window.
+// the pages of the range are then iterated without overflow
+fn check_user_range(addr: u32, size: u32) -> Result<(), SyscallError> {
+    match addr.checked_add(size) {
+        Some(end)
+            if addr >= paging::USER_MEMORY_BASE as u32
+                && end <= paging::PAGE_TABLE_WINDOW as u32 =>
+        {
+            Ok(())
+        }
+        _ => Err(SyscallError::InvalidArguments),
+    }
+}
+
 fn alloc(
     addr: u32,
     size: u32,
@@ -399,6 +1235,7 @@ fn alloc(
         paging::VirtAddr::new(addr)
     };
     let size = size as u32;
+    check_user_range(addr.to_u32(), size)?;
     let p = match perm::Perm::from_bits(perm_bits) {
         Some(x) => Ok(x),
         None => Err(SyscallError::InternalError),
@@ -414,7 +1251,235 @@ fn alloc(
 }
 
 fn free(addr: u32, size: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
-    unimplemented!()
+    check_user_range(addr, size)?;
+    k.current_process.as_mut().unwrap().mapper.free(
+        paging::VirtAddr::new(addr),
+        size,
+        &mut k.allocator,
+    )?;
+    Ok(0)
+}
+
+fn unmap(addr: u32, size: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    let addr = paging::VirtAddr::new(addr);
+    if !addr.is_page_aligned() {
+        return Err(SyscallError::InvalidAlignment);
+    }
+    check_user_range(addr.to_u32(), size)?;
+    k.current_process
+        .as_mut()
+        .unwrap()
+        .mapper
+        .free(addr, size, &mut k.allocator)?;
+    Ok(0)
+}
+
+fn open(path: u32, path_length: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    let (name, length) = copy_path_from_user(path, path_length, k)?;
+    let file = files::search(&mut k.filesystem, &name[..length]).map_err(from_file_error)?;
+    let p = k.current_process.as_mut().unwrap();
+    Ok(p.fds.alloc(fd::Descriptor::File(file))?)
+}
+
+fn close(fd: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    let desc = k.current_process.as_mut().unwrap().fds.close(fd)?;
+    k.close_descriptor(desc);
+    Ok(0)
+}
+
+// create a pipe, and store the fds of its read end and write end to fds_store
+fn pipe(fds_store: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    let pipe = k.pipes.alloc()?;
+    let result = store_pipe_fds(pipe, fds_store, k);
+    if result.is_err() {
+        // nobody refers to the pipe
+        k.pipes.close_reader(pipe);
+        k.pipes.close_writer(pipe);
+    }
+    result
+}
+
+fn store_pipe_fds(
+    pipe: usize,
+    fds_store: u32,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    let p = k.current_process.as_mut().unwrap();
+    let read_fd = p.fds.alloc(fd::Descriptor::PipeRead(pipe))?;
+    let fds = match p.fds.alloc(fd::Descriptor::PipeWrite(pipe)) {
+        Ok(write_fd) => [read_fd, write_fd],
+        Err(e) => {
+            p.fds.close(read_fd)?;
+            return Err(SyscallError::from(e));
+        }
+    };
+    if let Err(e) = usermem::write_to_user(&mut p.mapper, fds_store, &fds, &mut k.allocator) {
+        for fd in fds.iter() {
+            p.fds.close(*fd)?;
+        }
+        return Err(e);
+    }
+    Ok(0)
+}
+
+// make new_fd refer to what old_fd does. new_fd is closed first if it is open
+fn dup2(old_fd: u32, new_fd: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    let desc = k.current_process.as_ref().unwrap().fds.get(old_fd)?;
+    if old_fd == new_fd {
+        return Ok(new_fd);
+    }
+    let old = k
+        .current_process
+        .as_mut()
+        .unwrap()
+        .fds
+        .replace(new_fd, desc)?;
+    k.dup_descriptor(desc);
+    if let Some(old) = old {
+        k.close_descriptor(old);
+    }
+    Ok(new_fd)
+}
+
+// read from a pipe. the caller blocks while it is empty and some writer is left
+fn read_pipe(
+    pipe: usize,
+    buf: u32,
+    size: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    let result = {
+        let p = k.current_process.as_mut().unwrap();
+        let buf = usermem::user_slice_mut(&mut p.mapper, buf, size, &mut k.allocator)?;
+        k.pipes.read(pipe, buf)
+    };
+    match result {
+        Ok(n) => {
+            k.process_manager
+                .wake_up_all(proc::Event::PipeWritable(pipe));
+            Ok(n as u32)
+        }
+        Err(pipe::PipeError::Empty) => {
+            block_current_process(proc::Event::PipeReadable(pipe), ipc::WAIT_FOREVER, tf, k);
+            Ok(0)
+        }
+        Err(e) => Err(SyscallError::from(e)),
+    }
+}
+
+// write to a pipe. the caller blocks while it is full. only the bytes which fit in the
+// space are written
+fn write_pipe(
+    pipe: usize,
+    buf: u32,
+    size: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    let result = {
+        let p = k.current_process.as_ref().unwrap();
+        let buf = usermem::user_slice(&p.mapper, buf, size)?;
+        k.pipes.write(pipe, buf)
+    };
+    match result {
+        Ok(n) => {
+            k.process_manager
+                .wake_up_all(proc::Event::PipeReadable(pipe));
+            Ok(n as u32)
+        }
+        Err(pipe::PipeError::Full) => {
+            block_current_process(proc::Event::PipeWritable(pipe), ipc::WAIT_FOREVER, tf, k);
+            Ok(0)
+        }
+        Err(e) => Err(SyscallError::from(e)),
+    }
+}
+
+fn read(
+    fd: u32,
+    buf: u32,
+    size: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    if let fd::Descriptor::PipeRead(pipe) = k.current_process.as_ref().unwrap().fds.get(fd)? {
+        return read_pipe(pipe, buf, size, tf, k);
+    }
+    let p = k.current_process.as_mut().unwrap();
+    let buf = usermem::user_slice_mut(&mut p.mapper, buf, size, &mut k.allocator)?;
+    match p.fds.get_mut(fd)? {
+        fd::Descriptor::Uart => Ok(read_uart(buf)),
+        fd::Descriptor::File(ref mut file) => {
+            let fs = &mut k.filesystem;
+            // the offset may be past the end after lseek
+            let rest = fs
+                .size(file)
+                .map_err(from_file_error)?
+                .saturating_sub(fs.tell(file).map_err(from_file_error)?);
+            let n = cmp::min(size as usize, rest);
+            fs.read(file, buf, n).map_err(from_file_error)?;
+            Ok(n as u32)
+        }
+        // the read end is handled above
+        fd::Descriptor::PipeRead(_) | fd::Descriptor::PipeWrite(_) => {
+            Err(SyscallError::BadFileDescriptor)
+        }
+    }
+}
+
+fn write(
+    fd: u32,
+    buf: u32,
+    size: u32,
+    tf: &trap::TrapFrame,
+    k: &mut kernel::Kernel,
+) -> Result<u32, SyscallError> {
+    if let fd::Descriptor::PipeWrite(pipe) = k.current_process.as_ref().unwrap().fds.get(fd)? {
+        return write_pipe(pipe, buf, size, tf, k);
+    }
+    let p = k.current_process.as_mut().unwrap();
+    let buf = usermem::user_slice(&p.mapper, buf, size)?;
+    match p.fds.get_mut(fd)? {
+        fd::Descriptor::Uart => Ok(write_uart(buf)),
+        fd::Descriptor::File(ref mut file) => {
+            k.filesystem
+                .write(file, buf, size as usize)
+                .map_err(from_file_error)?;
+            Ok(size)
+        }
+        // the write end is handled above
+        fd::Descriptor::PipeRead(_) | fd::Descriptor::PipeWrite(_) => {
+            Err(SyscallError::BadFileDescriptor)
+        }
+    }
+}
+
+fn lseek(fd: u32, offset: u32, whence: u32, k: &mut kernel::Kernel) -> Result<u32, SyscallError> {
+    let whence = match Whence::from_u32(whence) {
+        Some(w) => w,
+        None => return Err(SyscallError::InvalidArguments),
+    };
+    let p = k.current_process.as_mut().unwrap();
+    let file: &mut File = match p.fds.get_mut(fd)? {
+        fd::Descriptor::File(ref mut file) => file,
+        fd::Descriptor::Uart | fd::Descriptor::PipeRead(_) | fd::Descriptor::PipeWrite(_) => {
+            return Err(SyscallError::InvalidArguments)
+        }
+    };
+    let fs = &mut k.filesystem;
+    let current = fs.tell(file).map_err(from_file_error)? as i32;
+    let target = match whence {
+        Whence::Set => Some(offset as i32),
+        Whence::Current => current.checked_add(offset as i32),
+        Whence::End => (fs.size(file).map_err(from_file_error)? as i32).checked_add(offset as i32),
+    };
+    let target = match target {
+        Some(target) if target >= 0 => target,
+        _ => return Err(SyscallError::InvalidArguments),
+    };
+    fs.seek(file, target - current).map_err(from_file_error)?;
+    Ok(target as u32)
 }
 
 pub fn syscall_dispatch(
@@ -424,11 +1489,11 @@ pub fn syscall_dispatch(
 ) -> Result<u32, SyscallError> {
     dprintln!("{:?}", sc);
     match sc {
-        Syscall::UartRead { buf, size } => uart_read(buf, size),
-        Syscall::UartWrite { buf, size } => uart_write(buf, size),
+        Syscall::UartRead { buf, size } => uart_read(buf, size, k),
+        Syscall::UartWrite { buf, size } => uart_write(buf, size, k),
         Syscall::Exit { status } => exit(status, k),
         Syscall::GetProcId => get_proc_id(k),
-        Syscall::Yield => yield_process(k),
+        Syscall::Yield => yield_process(tf, k),
         Syscall::Fork => fork(k, tf),
         Syscall::Execve {
             filename,
@@ -437,8 +1502,16 @@ pub fn syscall_dispatch(
             envp,
         } => execve(filename, filename_length, argv, envp, tf, k),
         Syscall::CheckProcessStatus { id } => check_process_status(id, k),
-        Syscall::SendData { id, data } => send_data(id, data, k),
-        Syscall::ReceiveData { data_store } => receive_data(data_store, k),
+        Syscall::SendData { id, data, timeout } => send_data(id, data, timeout, tf, k),
+        Syscall::ReceiveData {
+            data_store,
+            timeout,
+        } => receive_data(None, data_store, timeout, tf, k),
+        Syscall::ReceiveFrom {
+            id,
+            data_store,
+            timeout,
+        } => receive_data(Some(proc::Id(id)), data_store, timeout, tf, k),
         Syscall::Map {
             src_id,
             src_addr,
@@ -448,5 +1521,32 @@ pub fn syscall_dispatch(
         } => mmap(src_id, src_addr, dst_id, dst_addr, perm, k),
         Syscall::Alloc { addr, size, perm } => alloc(addr, size, perm, k),
         Syscall::Free { addr, size } => free(addr, size, k),
+        Syscall::Unmap { addr, size } => unmap(addr, size, k),
+        Syscall::Open { path, path_length } => open(path, path_length, k),
+        Syscall::Close { fd } => close(fd, k),
+        Syscall::Read { fd, buf, size } => read(fd, buf, size, tf, k),
+        Syscall::Write { fd, buf, size } => write(fd, buf, size, tf, k),
+        Syscall::Lseek { fd, offset, whence } => lseek(fd, offset, whence, k),
+        Syscall::Wait { id, status_store } => wait(id, status_store, tf, k),
+        Syscall::SetPriority { id, priority } => set_priority(id, priority, k),
+        Syscall::Sleep { usec } => sleep(usec, tf, k),
+        Syscall::SendBuffer {
+            id,
+            buf,
+            len,
+            timeout,
+        } => send_buffer(id, buf, len, timeout, tf, k),
+        Syscall::ReceiveBuffer {
+            buf,
+            size,
+            len_store,
+            timeout,
+        } => receive_buffer(buf, size, len_store, timeout, tf, k),
+        Syscall::SetQueueCapacity { capacity } => set_queue_capacity(capacity, k),
+        Syscall::Call { id, data } => call(id, data, tf, k),
+        Syscall::ReplyReceive { reply, data_store } => reply_receive(reply, data_store, tf, k),
+        Syscall::Pipe { fds_store } => pipe(fds_store, k),
+        Syscall::Dup2 { old_fd, new_fd } => dup2(old_fd, new_fd, k),
+        Syscall::Grant { peer, addr, perm } => grant(peer, addr, perm, k),
     }
 }
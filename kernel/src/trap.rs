@@ -1,9 +1,11 @@
+use backtrace;
 use core::fmt;
+use coredump;
 use csr;
 use csr::CSRRead;
 use kernel;
+use osmium_syscall::process::ExitStatus;
 use paging;
-use proc;
 use stvec;
 use syscall;
 
@@ -11,7 +13,11 @@ extern "C" {
     static trap_entry: u8;
 }
 
-const TIMER_INTERVAL: u64 = 10000000;
+// the length of a time slice, in microseconds
+const TIME_SLICE_US: u64 = 10_000;
+
+// the trap being handled, and whether it was taken in the kernel. dumped on panic
+static mut CURRENT_TRAP: Option<(TrapFrame, bool)> = None;
 
 #[derive(Copy, Clone, Debug)]
 pub enum Trap {
@@ -172,6 +178,40 @@ impl Exception {
             Exception::StorePageFault => 15 << 1,
         }
     }
+    // exception code defined in the privileged spec
+    pub fn code(self) -> u32 {
+        match self {
+            Exception::InstructionAddressMisaligned => 0,
+            Exception::InstructionAccessFault => 1,
+            Exception::IllegalInstruction => 2,
+            Exception::Breakpoint => 3,
+            Exception::LoadAccessMisaligned => 4,
+            Exception::LoadAccessFault => 5,
+            Exception::StoreAddressMisalinged => 6,
+            Exception::StoreAccessFault => 7,
+            Exception::EnvironmentCallU => 8,
+            Exception::EnvironmentCallS => 9,
+            Exception::EnvironmentCallM => 11,
+            Exception::InstructionPageFault => 12,
+            Exception::LoadPageFault => 13,
+            Exception::StorePageFault => 15,
+        }
+    }
+    // signal number reported in core files
+    pub fn signal(self) -> u32 {
+        const SIGILL: u32 = 4;
+        const SIGTRAP: u32 = 5;
+        const SIGBUS: u32 = 7;
+        const SIGSEGV: u32 = 11;
+        match self {
+            Exception::IllegalInstruction => SIGILL,
+            Exception::Breakpoint => SIGTRAP,
+            Exception::InstructionAddressMisaligned
+            | Exception::LoadAccessMisaligned
+            | Exception::StoreAddressMisalinged => SIGBUS,
+            _ => SIGSEGV,
+        }
+    }
     // use bitflags
     pub fn from_u32(x: u32) -> Option<Exception> {
         match x {
@@ -232,6 +272,10 @@ impl Register {
     pub fn set_syscall_result(&mut self, x: u32) {
         self.int_regs[10] = x;
     }
+    // the second return value, used by SYS_CALL
+    pub fn set_a1(&mut self, x: u32) {
+        self.int_regs[11] = x;
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -251,6 +295,47 @@ impl TrapFrame {
     }
 }
 
+const REGISTER_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+impl fmt::Display for TrapFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the slot of sp holds scause (see trap_entry)
+        write!(
+            f,
+            "pc     = {:08x}  sp     = {:08x}  scause = {:08x}",
+            self.pc, self.sp, self.regs.int_regs[2]
+        )?;
+        let regs = (1..32).filter(|&i| i != 2);
+        for (n, i) in regs.enumerate() {
+            if n % 4 == 0 {
+                writeln!(f)?;
+            } else {
+                write!(f, "  ")?;
+            }
+            write!(
+                f,
+                "{:<6} = {:08x}",
+                REGISTER_NAMES[i], self.regs.int_regs[i]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// print the trap being handled. called by the panic handler
+pub fn dump_current_trap() {
+    if let Some((tf, in_kernel)) = unsafe { CURRENT_TRAP } {
+        println!("while handling the trap:\n{}", tf);
+        if in_kernel {
+            backtrace::print_trap_backtrace(&tf);
+        }
+    }
+}
+
 pub fn trap_init() {
     dprintln!("setting stvec");
     stvec::STVEC::set_mode(stvec::Mode::Direct);
@@ -262,6 +347,7 @@ pub fn trap_init() {
 pub fn handle_envcall(mut tf: TrapFrame) -> ! {
     tf.pc += 4;
     let kernel = unsafe { kernel::get_kernel() };
+    let caller = kernel.current_process_id();
     let e = match syscall::Syscall::from_trap_frame(&tf) {
         Ok(syscall) => syscall::syscall_dispatch(syscall, kernel, &mut tf),
         Err(e) => {
@@ -270,78 +356,127 @@ pub fn handle_envcall(mut tf: TrapFrame) -> ! {
         }
     };
     match e {
-        Ok(result) => {
-            tf.regs.set_syscall_result(result);
-            kernel.update_current_process_trap_frame(tf);
-
-            kernel.run_into_user()
-        }
-        Err(e) => {
-            tf.regs.set_syscall_result(e.to_syscall_result() as u32);
-            kernel.update_current_process_trap_frame(tf);
-            kernel.run_into_user()
-        }
+        Ok(result) => tf.regs.set_syscall_result(result),
+        Err(e) => tf.regs.set_syscall_result(e.to_syscall_result() as u32),
     }
+    // the syscall may have switched to another process (e.g. SYS_CALL)
+    if kernel.current_process_id() == caller {
+        kernel.update_current_process_trap_frame(tf);
+    }
+    kernel.run_into_user()
+}
+
+// terminate the current process which caused `exc`, and run the next one
+// whether the trap being handled was taken in supervisor mode
+fn trapped_in_kernel() -> bool {
+    unsafe { CURRENT_TRAP }.map_or(false, |(_, in_kernel)| in_kernel)
 }
 
-fn kill_process_by_exception(mut tf: TrapFrame, k: &mut kernel::Kernel) {}
+fn kill_process_by_exception(exc: Exception, tf: TrapFrame) -> ! {
+    let k = unsafe { kernel::get_kernel() };
+    // a fault in the kernel (e.g. during a syscall) leaves the kernel state broken, so it
+    // cannot be blamed on the current process
+    let id = match k.current_process {
+        Some(ref p) if !trapped_in_kernel() => p.id,
+        _ => panic!(
+            "{} in kernel (stval = {:x})",
+            exc,
+            csr::stval::STVAL::read_csr()
+        ),
+    };
+    println!(
+        "process {} is killed by {} (pc = {:x}, stval = {:x})",
+        id.to_u32(),
+        exc,
+        tf.pc,
+        csr::stval::STVAL::read_csr()
+    );
+    let dumped = match k.current_process {
+        Some(ref p) => coredump::dump(&mut k.filesystem, p, &tf, exc.signal()),
+        None => unreachable!(),
+    };
+    match dumped {
+        Ok(()) => println!("core dumped to /core.{}", id.to_u32()),
+        Err(e) => println!("failed to dump core: {:?}", e),
+    }
+    match k.exit_current_process(ExitStatus::killed_by_exception(exc.code())) {
+        Ok(()) => (),
+        Err(e) => panic!("failed to kill process {}: {}", id.to_u32(), e),
+    }
+    k.run_into_user()
+}
 
-fn handle_store_page_fault(mut tf: TrapFrame) -> ! {
+fn handle_store_page_fault(tf: TrapFrame) -> ! {
     let k = unsafe { kernel::get_kernel() };
+    // the kernel copies CoW pages before writing to user memory (see usermem), and tf is
+    // not a user context to resume
+    if trapped_in_kernel() {
+        panic!(
+            "{} in kernel (stval = {:x})",
+            Exception::StorePageFault,
+            csr::stval::STVAL::read_csr()
+        );
+    }
 
     let stval = csr::stval::STVAL::read();
     let addr = paging::VirtAddr::new(paging::VirtAddr::new(stval.val).page_start_addr());
-    if k.current_process
+    let is_cow = match k.current_process {
+        Some(ref p) => p
+            .mapper
+            .check_perm(addr, paging::Flag::COW | paging::Flag::USER),
+        None => false,
+    };
+    if !is_cow {
+        kill_process_by_exception(Exception::StorePageFault, tf)
+    }
+    // handle cow
+    dprintln!("handle cow");
+    match k
+        .current_process
         .as_mut()
         .unwrap()
         .mapper
-        .check_perm(addr, paging::Flag::COW)
+        .clone_page(paging::Page::from_addr(addr), &mut k.allocator)
     {
-        // handle cow
-        dprintln!("handle cow");
-        let frame = &mut k
-            .current_process
-            .as_mut()
-            .unwrap()
-            .mapper
-            .clone_page(paging::Page::from_addr(addr), &mut k.allocator);
-    } else {
-        // kill process
-        unimplemented!();
+        Ok(()) => (),
+        Err(e) => {
+            println!("failed to copy the page: {}", e);
+            kill_process_by_exception(Exception::StorePageFault, tf)
+        }
     }
-    dprintln!("Good luck");
-    k.run_into_user()
-}
-fn handle_load_page_fault(mut tf: TrapFrame) -> ! {
-    let k = unsafe { kernel::get_kernel() };
+    // retry the store instruction
+    k.update_current_process_trap_frame(tf);
     k.run_into_user()
 }
-fn handle_instr_page_fault(mut tf: TrapFrame) -> ! {
-    unimplemented!()
-}
-fn handle_access_fault(mut tf: TrapFrame) -> ! {
-    unimplemented!()
-}
 
 fn exception_handler(exc: Exception, tf: TrapFrame) -> ! {
     match exc {
         Exception::EnvironmentCallU => handle_envcall(tf),
-        Exception::LoadPageFault => handle_load_page_fault(tf),
         Exception::StorePageFault => handle_store_page_fault(tf),
-        Exception::InstructionPageFault => handle_instr_page_fault(tf),
-        Exception::LoadAccessFault
-        | Exception::StoreAccessFault
-        | Exception::InstructionAccessFault => handle_access_fault(tf),
-        _ => panic!("{} is not supported", exc.to_str()),
+        _ => kill_process_by_exception(exc, tf),
     }
 }
 
-fn handle_timer(mut tf: TrapFrame) -> ! {
+fn handle_timer(tf: TrapFrame) -> ! {
     let k = unsafe { kernel::get_kernel() };
-    k.current_process.as_mut().unwrap().status = proc::Status::Runnable;
-    k.current_process = None;
     csr::sip::SIP::timer_off();
-    csr::timer::set_interval(csr::timer::MicroSeccond::new(TIMER_INTERVAL));
+    let now = csr::timer::now();
+    // woken sleepers may have a higher priority than the current process
+    if k.process_manager.wake_up_sleepers(now) || k.time_slice_end <= now {
+        k.preempt_current_process(tf);
+        k.time_slice_end = now + csr::timer::ms2clk(csr::timer::MicroSeccond::new(TIME_SLICE_US));
+    } else {
+        k.update_current_process_trap_frame(tf);
+    }
+    k.set_timer();
+    k.run_into_user();
+}
+
+// nothing is done for the interrupt itself. the interrupted process is resumed, or the
+// kernel goes back to idle if the interrupt is taken there
+fn resume_after_interrupt(tf: TrapFrame) -> ! {
+    let k = unsafe { kernel::get_kernel() };
+    k.update_current_process_trap_frame(tf);
     k.run_into_user();
 }
 
@@ -350,7 +485,12 @@ fn interruption_handler(itrpt: Interruption, tf: TrapFrame) -> ! {
         Interruption::MachineTimer | Interruption::SupervisorTimer | Interruption::UserTimer => {
             handle_timer(tf)
         }
-        Interruption::SupervisorSoftware => handle_timer(tf),
+        Interruption::SupervisorSoftware => {
+            csr::sip::SIP::software_off();
+            resume_after_interrupt(tf)
+        }
+        // no device raises it yet
+        Interruption::SupervisorExternal => resume_after_interrupt(tf),
         _ => panic!("{} is not supported", itrpt.to_str()),
     }
 }
@@ -382,6 +522,11 @@ fn trap(tf: TrapFrame) -> ! {
         tf.sp
     );
 
+    // SPP: the trap was taken in supervisor mode
+    unsafe {
+        CURRENT_TRAP = Some((tf, (sstatus >> 8) & 1 == 1));
+    }
+
     match trap {
         Trap::Exception(e) => exception_handler(e, tf),
         Trap::Interruption(i) => interruption_handler(i, tf),
@@ -486,6 +631,11 @@ pub unsafe fn pop_trap_frame(tf: &TrapFrame) -> ! {
         sp
     );
 
+    // the trap may have been taken in the idle loop of the kernel, so always return to user
+    // mode with interrupts enabled
+    csr::sstatus::SSTATUS::spp_user();
+    csr::sstatus::SSTATUS::spie_on();
+
     asm!(
         "
         csrrw x0, sscratch, $0\n
@@ -0,0 +1,144 @@
+use core::mem;
+use core::slice;
+use osmium_syscall::errors::SyscallError;
+use paging;
+
+// Checked access to the memory of a user process.
+// `map` must be the page table which is currently set to satp.
+
+// call f on every page which [addr, addr + size) overlaps
+fn for_each_page<F>(addr: u32, size: u32, mut f: F) -> Result<(), SyscallError>
+where
+    F: FnMut(paging::VirtAddr) -> Result<(), SyscallError>,
+{
+    if size == 0 {
+        return Ok(());
+    }
+    let last = match addr.checked_add(size - 1) {
+        Some(last) => paging::VirtAddr::new(last).page_start_addr(),
+        None => return Err(SyscallError::BadAddress),
+    };
+    let mut page = paging::VirtAddr::new(addr).page_start_addr();
+    loop {
+        f(paging::VirtAddr::new(page))?;
+        if page == last {
+            return Ok(());
+        }
+        page += paging::PGSIZE as u32;
+    }
+}
+
+pub fn check_readable(map: &paging::Map, addr: u32, size: u32) -> Result<(), SyscallError> {
+    let flag = paging::Flag::VALID | paging::Flag::USER | paging::Flag::READ;
+    for_each_page(addr, size, |page| {
+        if map.check_perm(page, flag) {
+            Ok(())
+        } else {
+            Err(SyscallError::BadAddress)
+        }
+    })
+}
+
+// CoW pages in the range are copied here so that the kernel can write to them
+pub fn check_writable(
+    map: &mut paging::Map,
+    addr: u32,
+    size: u32,
+    allocator: &mut paging::Allocator,
+) -> Result<(), SyscallError> {
+    let flag = paging::Flag::VALID | paging::Flag::USER | paging::Flag::READ;
+    for_each_page(addr, size, |page| {
+        if !map.check_perm(page, flag) {
+            Err(SyscallError::BadAddress)
+        } else if map.check_perm(page, paging::Flag::WRITE) {
+            Ok(())
+        } else if map.check_perm(page, paging::Flag::COW) {
+            map.clone_page(paging::Page::from_addr(page), allocator)?;
+            Ok(())
+        } else {
+            Err(SyscallError::BadAddress)
+        }
+    })
+}
+
+pub fn user_slice<'b>(map: &paging::Map, addr: u32, size: u32) -> Result<&'b [u8], SyscallError> {
+    check_readable(map, addr, size)?;
+    if size == 0 {
+        return Ok(&[]);
+    }
+    Ok(unsafe { slice::from_raw_parts(addr as *const u8, size as usize) })
+}
+
+pub fn user_slice_mut<'b>(
+    map: &mut paging::Map,
+    addr: u32,
+    size: u32,
+    allocator: &mut paging::Allocator,
+) -> Result<&'b mut [u8], SyscallError> {
+    check_writable(map, addr, size, allocator)?;
+    if size == 0 {
+        return Ok(&mut []);
+    }
+    Ok(unsafe { slice::from_raw_parts_mut(addr as *mut u8, size as usize) })
+}
+
+pub fn copy_from_user(map: &paging::Map, dst: &mut [u8], src: u32) -> Result<(), SyscallError> {
+    let src = user_slice(map, src, dst.len() as u32)?;
+    dst.copy_from_slice(src);
+    Ok(())
+}
+
+pub fn copy_to_user(
+    map: &mut paging::Map,
+    dst: u32,
+    src: &[u8],
+    allocator: &mut paging::Allocator,
+) -> Result<(), SyscallError> {
+    let dst = user_slice_mut(map, dst, src.len() as u32, allocator)?;
+    dst.copy_from_slice(src);
+    Ok(())
+}
+
+// read a value byte by byte, so that src need not be aligned
+pub fn read_from_user<T: Copy>(
+    map: &paging::Map,
+    src: u32,
+    value: &mut T,
+) -> Result<(), SyscallError> {
+    let dst = unsafe { slice::from_raw_parts_mut(value as *mut T as *mut u8, mem::size_of::<T>()) };
+    copy_from_user(map, dst, src)
+}
+
+// write a value byte by byte, so that dst need not be aligned
+pub fn write_to_user<T: Copy>(
+    map: &mut paging::Map,
+    dst: u32,
+    value: &T,
+    allocator: &mut paging::Allocator,
+) -> Result<(), SyscallError> {
+    let src = unsafe { slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>()) };
+    copy_to_user(map, dst, src, allocator)
+}
+
+#[test]
+fn test_for_each_page() {
+    let mut pages = [0u32; 4];
+    let mut n = 0;
+    for_each_page(0x1ff0, 0x20, |page| {
+        pages[n] = page.to_u32();
+        n += 1;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(&pages[..n], &[0x1000, 0x2000]);
+
+    n = 0;
+    for_each_page(0x3000, 0, |_| {
+        n += 1;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(n, 0);
+
+    assert!(for_each_page(0xffff_f000, 0x2000, |_| Ok(())).is_err());
+}
@@ -0,0 +1,84 @@
+use indexlist::{IndexList, List};
+use pipe::N_PIPES;
+use proc::{Event, N_PROCS};
+
+// FIFO queues of the processes waiting for the same event, so that the waiters are found
+// without scanning all the processes. events which only wake up the waiting process itself
+// (ChildExit, Timer and Message) are not queued.
+
+const MESSAGE_FROM: usize = 0;
+const QUEUE_SPACE: usize = MESSAGE_FROM + N_PROCS;
+const REPLY: usize = QUEUE_SPACE + N_PROCS;
+const PIPE_READABLE: usize = REPLY + N_PROCS;
+const PIPE_WRITABLE: usize = PIPE_READABLE + N_PIPES;
+const N_QUEUES: usize = PIPE_WRITABLE + N_PIPES;
+
+// the queue of the processes waiting for `event`
+fn queue_of(event: Event) -> Option<usize> {
+    match event {
+        Event::MessageFrom(id) => Some(MESSAGE_FROM + id.0 as usize),
+        Event::QueueSpace(id) => Some(QUEUE_SPACE + id.0 as usize),
+        Event::Reply(id) => Some(REPLY + id.0 as usize),
+        Event::PipeReadable(pipe) => Some(PIPE_READABLE + pipe),
+        Event::PipeWritable(pipe) => Some(PIPE_WRITABLE + pipe),
+        Event::ChildExit | Event::Timer | Event::Message => None,
+    }
+}
+
+pub struct WaitQueue {
+    links: IndexList,
+    queues: [List; N_QUEUES],
+}
+
+impl WaitQueue {
+    pub fn new() -> WaitQueue {
+        WaitQueue {
+            links: IndexList::new(),
+            queues: [List::new(); N_QUEUES],
+        }
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        self.links.contains(index)
+    }
+
+    // push the process at the tail of the queue of `event`. a process waits for one event at
+    // a time, so it is moved if it is in another queue
+    pub fn push(&mut self, index: usize, event: Event) {
+        match queue_of(event) {
+            Some(queue) => self.links.push_back(&mut self.queues, queue, index),
+            None => self.remove(index),
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        self.links.remove(&mut self.queues, index);
+    }
+
+    // the process which started waiting for `event` first
+    pub fn first(&self, event: Event) -> Option<usize> {
+        self.queues[queue_of(event)?].first()
+    }
+}
+
+#[test]
+fn test_wait_queue() {
+    use proc::Id;
+    let mut q = WaitQueue::new();
+    let event = Event::QueueSpace(Id(1));
+    q.push(3, event);
+    q.push(2, event);
+    q.push(4, Event::Reply(Id(1)));
+    q.push(5, Event::PipeReadable(N_PIPES - 1));
+    // not queued
+    q.push(6, Event::Timer);
+    assert!(!q.contains(6));
+    assert_eq!(q.first(Event::Timer), None);
+    assert_eq!(q.first(event), Some(3));
+    assert_eq!(q.first(Event::Reply(Id(1))), Some(4));
+    assert_eq!(q.first(Event::PipeReadable(N_PIPES - 1)), Some(5));
+    assert_eq!(q.first(Event::PipeWritable(N_PIPES - 1)), None);
+    // waiting for another event moves the process
+    q.push(3, Event::Timer);
+    assert_eq!(q.first(event), Some(2));
+}
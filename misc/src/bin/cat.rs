@@ -0,0 +1,43 @@
+#![no_main]
+#![no_std]
+
+#[macro_use]
+extern crate misc;
+extern crate osmium_syscall;
+
+use misc::syscall;
+use osmium_syscall::file;
+
+// cat [file]: print the file, or the stdin until EOF
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let fd = match misc::env::args().nth(1) {
+        Some(path) => match syscall::sys_open(path) {
+            Ok(fd) => fd,
+            Err(e) => {
+                println!("cat: {}: {}", path, e);
+                syscall::sys_exit(1);
+            }
+        },
+        None => file::STDIN,
+    };
+    let mut buf = [0u8; 256];
+    loop {
+        let len = match syscall::sys_read(fd, &mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) => {
+                println!("cat: {}", e);
+                syscall::sys_exit(1);
+            }
+        };
+        let mut rest = &buf[..len];
+        while rest.len() > 0 {
+            match syscall::sys_write(file::STDOUT, rest) {
+                Ok(n) => rest = &rest[n..],
+                Err(_) => syscall::sys_exit(1),
+            }
+        }
+    }
+    syscall::sys_exit(0);
+}
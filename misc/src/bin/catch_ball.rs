@@ -11,35 +11,8 @@ use misc::syscall;
 use osmium_syscall::errors::SyscallError;
 use osmium_syscall::perm;
 
-fn blocking_receive() -> Result<syscall::Message, SyscallError> {
-    //println!("blocking_receive");
-    match syscall::sys_receive() {
-        Err(SyscallError::QueueIsEmpty) => {
-            syscall::sys_yield();
-            blocking_receive()
-        }
-        Err(e) => Err(e),
-        Ok(msg) => Ok(msg),
-    }
-}
-
 fn receive_from_id(id: u32) -> Result<u32, SyscallError> {
-    match blocking_receive() {
-        Ok(ref msg) if msg.id == id => Ok(msg.data),
-        Ok(ref msg) => receive_from_id(id),
-        Err(e) => Err(e),
-    }
-}
-
-fn blocking_send(id: u32, data: u32) -> Result<(), SyscallError> {
-    match syscall::sys_send(id, data) {
-        Err(SyscallError::QueueIsFull) => {
-            syscall::sys_yield();
-            blocking_send(id, data)
-        }
-        Err(e) => Err(e),
-        Ok(()) => Ok(()),
-    }
+    Ok(syscall::sys_receive_from_blocking(id, None)?.data)
 }
 
 const SIZE: usize = 8;
@@ -48,12 +21,9 @@ fn parent(child_id: u32) -> Result<(), SyscallError> {
     syscall::sys_send(child_id, my_id)?;
 
     let mut data = [1; SIZE];
-    for i in 0..5 {
-        for x in &data {
-            blocking_send(child_id, *x)?;
-        }
+    for _ in 0..5 {
         for i in 0..SIZE {
-            let v = receive_from_id(child_id)?;
+            let v = syscall::sys_call(child_id, data[i])?;
             data[i] = (i as u32 + 1) * v;
         }
     }
@@ -66,16 +36,10 @@ fn parent(child_id: u32) -> Result<(), SyscallError> {
     let addr = syscall::sys_alloc(None, 4096 * 2, perm::Perm::READ | perm::Perm::WRITE)?;
     println!("addr: {}", addr);
 
-    // second, memory map between the parent and its child
-    syscall::sys_mmap(
-        my_id,
-        addr,
-        child_id,
-        addr,
-        perm::Perm::READ | perm::Perm::WRITE,
-    )?;
+    // second, allow the child to map the page
+    syscall::sys_grant(child_id, addr, perm::Perm::READ | perm::Perm::WRITE)?;
 
-    // then, notify the address where they use
+    // then, notify the address where they use. the child maps it by itself
     syscall::sys_send(child_id, addr)?;
 
     // start communicating.
@@ -89,21 +53,22 @@ fn parent(child_id: u32) -> Result<(), SyscallError> {
 }
 
 fn child() -> Result<(), SyscallError> {
-    let id = syscall::sys_receive()?.data;
+    let id = syscall::sys_receive_blocking(None)?.data;
     // should check id is my parent
-    let mut data = [1; SIZE];
-    for _ in 0..5 {
-        for i in 0..SIZE {
-            let v = receive_from_id(id)?;
-            data[i] = v + 1;
-        }
-        for x in &data {
-            blocking_send(id, *x)?;
-        }
+    let mut request = syscall::sys_receive_blocking(None)?;
+    for _ in 1..5 * SIZE {
+        request = syscall::sys_reply_recv(request.data + 1)?;
     }
-
-    let addr = receive_from_id(id)?;
+    // reply to the last call, and wait for the address
+    let addr = syscall::sys_reply_recv(request.data + 1)?.data;
     println!("child addr: {}", addr);
+    syscall::sys_mmap(
+        id,
+        addr,
+        syscall::sys_get_proc_id(),
+        addr,
+        perm::Perm::READ | perm::Perm::WRITE,
+    )?;
     let data = unsafe { slice::from_raw_parts_mut(addr as *mut u32, 4096 / 4) };
 
     // wait for update
@@ -7,6 +7,9 @@ extern crate misc;
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
     println!("{}", "Hello Syscall");
+    for (i, arg) in misc::env::args().enumerate() {
+        println!("argv[{}] = {}", i, arg);
+    }
     misc::syscall::sys_exit(0);
     loop {}
 }
\ No newline at end of file
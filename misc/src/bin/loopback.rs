@@ -12,7 +12,7 @@ pub extern "C" fn _start() -> ! {
     let mut buf = [0u8; 16];
     loop {
         let len = buf.len();
-        syscall::sys_read(&mut buf, len);
-        syscall::sys_write(&buf, len);
+        syscall::sys_uart_read(&mut buf, len);
+        syscall::sys_uart_write(&buf, len);
     }
 }
\ No newline at end of file
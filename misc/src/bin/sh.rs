@@ -4,10 +4,19 @@
 
 #[macro_use]
 extern crate misc;
+extern crate osmium_syscall;
 
+use core::cmp;
 use core::str;
 use misc::syscall;
 use misc::uart;
+use osmium_syscall::file;
+use osmium_syscall::process;
+
+const MAX_ARGS: usize = 16;
+const MAX_PATH_LENGTH: usize = 256;
+const NICE_INCREMENT: u32 = 4;
+const MAX_COMMANDS: usize = 8;
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
@@ -23,35 +32,162 @@ pub extern "C" fn _start() -> ! {
             println!("Sorry. too long. Please enter shorter command");
             continue;
         }
-        let cmd = match str::from_utf8(&buf) {
+        let cmd = match str::from_utf8(&buf[..len]) {
             Ok(s) => s,
             Err(_) => {
                 println!("Failed to parse your input. Try again.");
                 continue;
             }
         };
-        if buf[0] == b'e' && buf[1] == b'x' && buf[2] == b'i' && buf[3] == b't'  && len == 4{
+        // a | b | ...: the commands of a pipeline
+        let mut commands = [[""; MAX_ARGS]; MAX_COMMANDS];
+        let mut argcs = [0; MAX_COMMANDS];
+        let mut n_commands = 0;
+        let mut error = None;
+        for command in cmd.split('|') {
+            if n_commands == MAX_COMMANDS {
+                error = Some("Sorry. too many commands");
+                break;
+            }
+            let argv = &mut commands[n_commands];
+            let mut argc = 0;
+            for word in command.split_whitespace() {
+                if argc == MAX_ARGS {
+                    break;
+                }
+                argv[argc] = word;
+                argc += 1;
+            }
+            if argc == MAX_ARGS {
+                error = Some("Sorry. too many arguments");
+                break;
+            }
+            argcs[n_commands] = argc;
+            n_commands += 1;
+        }
+        if let Some(error) = error {
+            println!("{}", error);
+            continue;
+        }
+        if n_commands == 1 && argcs[0] == 0 {
+            continue;
+        }
+        if argcs[..n_commands].iter().any(|argc| *argc == 0) {
+            println!("Sorry. empty command in the pipeline");
+            continue;
+        }
+        if n_commands == 1 && commands[0][0] == "exit" {
             syscall::sys_exit(0);
         }
-        match syscall::sys_fork() {
-            syscall::ForkResult::Parent(id) => {
-                loop {
-                    match syscall::sys_check_process_status(id) {
-                        syscall::ProcessStatus::Running | syscall::ProcessStatus::Runnable => {
-                            syscall::sys_yield();
-                        },
-                        _ => {
-                            break;
-                        }
+        // connect the stdout of each command to the stdin of the next one
+        let mut children = [0; MAX_COMMANDS];
+        let mut n_children = 0;
+        let mut stdin = None;
+        for i in 0..n_commands {
+            let (read_end, stdout) = if i + 1 == n_commands {
+                (None, None)
+            } else {
+                match syscall::sys_pipe() {
+                    Ok((read_end, write_end)) => (Some(read_end), Some(write_end)),
+                    Err(e) => {
+                        println!("pipe failed: {}", e);
+                        break;
                     }
                 }
-            },
-            syscall::ForkResult::Fail => {
-                println!("fork failed");
-            },
-            syscall::ForkResult::Child => {
-                syscall::sys_execve(cmd, len as u32, &[], &[]);
+            };
+            if let Some(id) = spawn(&commands[i][..argcs[i]], stdin, stdout, read_end) {
+                children[n_children] = id;
+                n_children += 1;
+            }
+            // the ends are held by the children
+            for fd in stdin.iter().chain(stdout.iter()) {
+                let _ = syscall::sys_close(*fd);
+            }
+            stdin = read_end;
+        }
+        if let Some(fd) = stdin {
+            let _ = syscall::sys_close(fd);
+        }
+        for id in children[..n_children].iter() {
+            match syscall::sys_wait(Some(*id)) {
+                Ok(result) => {
+                    if let Some(code) = result.status.killed_by() {
+                        println!("process {} was killed by exception {}", result.id, code);
+                    }
+                }
+                Err(e) => println!("wait failed: {}", e),
+            }
+        }
+    }
+}
+
+// replace the fd `to` of the caller with `fd`
+fn redirect(fd: Option<u32>, to: u32) {
+    if let Some(fd) = fd {
+        if let Err(e) = syscall::sys_dup2(fd, to) {
+            println!("dup2 failed: {}", e);
+            syscall::sys_exit(1);
+        }
+        let _ = syscall::sys_close(fd);
+    }
+}
+
+// run a command in a child process, with its stdin and stdout replaced by the given fds.
+// `unused` is the other end of the pipe of stdout, which the child closes. the id of the
+// child is returned
+fn spawn(
+    argv: &[&str],
+    stdin: Option<u32>,
+    stdout: Option<u32>,
+    unused: Option<u32>,
+) -> Option<u32> {
+    // nice [increment] command: run the command with a lower priority
+    let mut argv = argv;
+    let mut priority = None;
+    if argv[0] == "nice" {
+        let (increment, rest) = match argv.get(1).and_then(|s| s.parse().ok()) {
+            Some(increment) => (increment, &argv[2..]),
+            None => (NICE_INCREMENT, &argv[1..]),
+        };
+        if rest.len() == 0 {
+            println!("usage: nice [increment] command");
+            return None;
+        }
+        priority = Some(cmp::min(
+            process::DEFAULT_PRIORITY.saturating_add(increment),
+            process::N_PRIORITIES as u32 - 1,
+        ));
+        argv = rest;
+    }
+    // programs are searched in /bin unless the path is given
+    let mut path = [0u8; MAX_PATH_LENGTH];
+    let prefix: &[u8] = if argv[0].starts_with('/') { b"" } else { b"/bin/" };
+    let path_len = prefix.len() + argv[0].len();
+    if path_len >= MAX_PATH_LENGTH {
+        println!("Sorry. too long command name");
+        return None;
+    }
+    path[..prefix.len()].copy_from_slice(prefix);
+    path[prefix.len()..path_len].copy_from_slice(argv[0].as_bytes());
+    let path = str::from_utf8(&path[..path_len]).unwrap();
+    match syscall::sys_fork() {
+        syscall::ForkResult::Parent(id) => Some(id),
+        syscall::ForkResult::Fail => {
+            println!("fork failed");
+            None
+        },
+        syscall::ForkResult::Child => {
+            redirect(stdin, file::STDIN);
+            redirect(stdout, file::STDOUT);
+            if let Some(fd) = unused {
+                let _ = syscall::sys_close(fd);
+            }
+            if let Some(priority) = priority {
+                if let Err(e) = syscall::sys_set_priority(None, priority) {
+                    println!("nice failed: {}", e);
+                }
             }
+            syscall::sys_execve(path, path_len as u32, argv, &[]);
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,21 @@
+#![no_main]
+#![no_std]
+
+#[macro_use]
+extern crate misc;
+
+use misc::syscall;
+
+// sleep <seconds>
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    let seconds: u32 = match misc::env::args().nth(1).map(|arg| arg.parse()) {
+        Some(Ok(seconds)) => seconds,
+        _ => {
+            println!("usage: sleep <seconds>");
+            syscall::sys_exit(1);
+        }
+    };
+    syscall::sys_sleep(seconds.saturating_mul(1000 * 1000));
+    syscall::sys_exit(0);
+}
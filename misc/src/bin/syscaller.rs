@@ -13,7 +13,7 @@ pub extern "C" fn _start() -> ! {
     println!("Please enter a byte");
 
     let mut buf = [0u8; 1];
-    syscall::sys_read(&mut buf, 1);
+    syscall::sys_uart_read(&mut buf, 1);
     println!("Good job > {}", str::from_utf8(&buf).unwrap());
 
     println!("My proc id is {:x}", syscall::sys_get_proc_id());
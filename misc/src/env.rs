@@ -0,0 +1,53 @@
+use core::slice;
+use core::str;
+use osmium_syscall::args::{Arg, ArgsInfo, ARGS_INFO_ADDR};
+
+// Access to the arguments which the kernel put on the stack at execve
+
+pub struct Args {
+    args: &'static [Arg],
+    index: usize,
+}
+
+impl Args {
+    fn new(ptr: u32, len: u32) -> Args {
+        let args = if len == 0 {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(ptr as *const Arg, len as usize) }
+        };
+        Args { args, index: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.args.len() - self.index
+    }
+}
+
+impl Iterator for Args {
+    type Item = &'static str;
+
+    fn next(&mut self) -> Option<&'static str> {
+        if self.index == self.args.len() {
+            return None;
+        }
+        let arg = self.args[self.index];
+        self.index += 1;
+        let bytes = unsafe { slice::from_raw_parts(arg.ptr as *const u8, arg.len as usize) };
+        Some(str::from_utf8(bytes).unwrap_or(""))
+    }
+}
+
+fn info() -> &'static ArgsInfo {
+    unsafe { &*(ARGS_INFO_ADDR as *const ArgsInfo) }
+}
+
+// argv given to execve. the first one is the program name by convention
+pub fn args() -> Args {
+    Args::new(info().argv, info().argc)
+}
+
+// envp given to execve
+pub fn envs() -> Args {
+    Args::new(info().envp, info().envc)
+}
@@ -6,6 +6,7 @@ extern crate osmium_syscall;
 
 #[macro_use]
 pub mod uart;
+pub mod env;
 pub mod syscall;
 
 use core::panic::PanicInfo;
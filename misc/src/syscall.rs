@@ -1,7 +1,11 @@
 use core::fmt;
+use osmium_syscall::args;
 use osmium_syscall::errors::SyscallError;
+use osmium_syscall::file;
+use osmium_syscall::ipc;
 use osmium_syscall::number;
 use osmium_syscall::perm;
+use osmium_syscall::process;
 
 fn syscall_0(num: u32) -> u32 {
     let result: u32;
@@ -81,11 +85,11 @@ fn syscall_5(num: u32, a: u32, b: u32, c: u32, d: u32, e: u32) -> u32 {
     result
 }
 
-pub fn sys_write(buf: &[u8], size: usize) -> u32 {
+pub fn sys_uart_write(buf: &[u8], size: usize) -> u32 {
     syscall_2(number::SYS_UART_WRITE, buf.as_ptr() as u32, size as u32)
 }
 
-pub fn sys_read(buf: &mut [u8], size: usize) -> u32 {
+pub fn sys_uart_read(buf: &mut [u8], size: usize) -> u32 {
     syscall_2(number::SYS_UART_READ, buf.as_ptr() as u32, size as u32)
 }
 
@@ -102,6 +106,11 @@ pub fn sys_yield() -> u32 {
     syscall_0(number::SYS_YIELD)
 }
 
+// block for `usec` microseconds
+pub fn sys_sleep(usec: u32) {
+    syscall_1(number::SYS_SLEEP, usec);
+}
+
 pub enum ForkResult {
     Parent(u32),
     Child,
@@ -118,12 +127,30 @@ pub fn sys_fork() -> ForkResult {
     }
 }
 
-pub fn sys_execve(
-    filename: &str,
-    filename_len: u32,
-    argv: &[*const u32],
-    envp: &[*const u32],
-) -> ! {
+// convert to a null-terminated array of args::Arg
+fn to_arg_array(strs: &[&str]) -> Result<[args::Arg; args::MAX_ARGS + 1], SyscallError> {
+    let mut array = [args::Arg::null(); args::MAX_ARGS + 1];
+    if strs.len() > args::MAX_ARGS {
+        return Err(SyscallError::InvalidArguments);
+    }
+    for (arg, s) in array.iter_mut().zip(strs.iter()) {
+        *arg = args::Arg {
+            ptr: s.as_ptr() as u32,
+            len: s.len() as u32,
+        };
+    }
+    Ok(array)
+}
+
+pub fn sys_execve(filename: &str, filename_len: u32, argv: &[&str], envp: &[&str]) -> ! {
+    let arrays = to_arg_array(argv).and_then(|argv| Ok((argv, to_arg_array(envp)?)));
+    let (argv, envp) = match arrays {
+        Ok(arrays) => arrays,
+        Err(e) => {
+            println!("{}", e);
+            sys_exit(e.to_syscall_result())
+        }
+    };
     let x = syscall_4(
         number::SYS_EXECVE,
         filename.as_bytes().as_ptr() as u32,
@@ -165,8 +192,49 @@ pub fn sys_check_process_status(id: u32) -> ProcessStatus {
     ProcessStatus::from_u32(r)
 }
 
-pub fn sys_send(id: u32, data: u32) -> Result<(), SyscallError> {
-    let r = syscall_2(number::SYS_SEND, id, data);
+pub struct WaitResult {
+    pub id: u32,
+    pub status: process::ExitStatus,
+}
+
+// wait for the child `id` to exit. None means any child.
+pub fn sys_wait(id: Option<u32>) -> Result<WaitResult, SyscallError> {
+    let id = match id {
+        Some(id) => id,
+        None => process::WAIT_ANY,
+    };
+    let mut status = process::ExitStatus::exited(0);
+    let r = syscall_2(
+        number::SYS_WAIT,
+        id,
+        (&mut status) as *mut process::ExitStatus as u32,
+    ) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(WaitResult {
+            id: r as u32,
+            status,
+        })
+    }
+}
+
+// change the priority of the child `id`, or of the caller if None. the old one is returned.
+pub fn sys_set_priority(id: Option<u32>, priority: u32) -> Result<u32, SyscallError> {
+    let id = match id {
+        Some(id) => id,
+        None => process::CURRENT_PROCESS,
+    };
+    let r = syscall_2(number::SYS_SET_PRIORITY, id, priority) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(r as u32)
+    }
+}
+
+fn send(id: u32, data: u32, timeout: u32) -> Result<(), SyscallError> {
+    let r = syscall_3(number::SYS_SEND, id, data, timeout);
     let r = r as i32;
     if r < 0 {
         Err(SyscallError::from_syscall_result(r))
@@ -175,14 +243,27 @@ pub fn sys_send(id: u32, data: u32) -> Result<(), SyscallError> {
     }
 }
 
+pub fn sys_send(id: u32, data: u32) -> Result<(), SyscallError> {
+    send(id, data, ipc::NO_WAIT)
+}
+
+// wait while the queue of `id` is full, for `timeout` microseconds or forever if None
+pub fn sys_send_blocking(id: u32, data: u32, timeout: Option<u32>) -> Result<(), SyscallError> {
+    send(id, data, timeout.unwrap_or(ipc::WAIT_FOREVER))
+}
+
 pub struct Message {
     pub id: u32,
     pub data: u32,
 }
 
-pub fn sys_receive() -> Result<Message, SyscallError> {
+fn receive(timeout: u32) -> Result<Message, SyscallError> {
     let mut data_store: u32 = 100;
-    let r = syscall_1(number::SYS_RECEIVE, (&mut data_store) as *mut u32 as u32);
+    let r = syscall_2(
+        number::SYS_RECEIVE,
+        (&mut data_store) as *mut u32 as u32,
+        timeout,
+    );
     let r = r as i32;
     if r < 0 {
         Err(SyscallError::from_syscall_result(r))
@@ -194,6 +275,148 @@ pub fn sys_receive() -> Result<Message, SyscallError> {
     }
 }
 
+pub fn sys_receive() -> Result<Message, SyscallError> {
+    receive(ipc::NO_WAIT)
+}
+
+// wait for a message, for `timeout` microseconds or forever if None
+pub fn sys_receive_blocking(timeout: Option<u32>) -> Result<Message, SyscallError> {
+    receive(timeout.unwrap_or(ipc::WAIT_FOREVER))
+}
+
+fn receive_from(id: u32, timeout: u32) -> Result<Message, SyscallError> {
+    let mut data_store: u32 = 0;
+    let r = syscall_3(
+        number::SYS_RECEIVE_FROM,
+        id,
+        (&mut data_store) as *mut u32 as u32,
+        timeout,
+    ) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(Message {
+            id: r as u32,
+            data: data_store,
+        })
+    }
+}
+
+// receive the first message from `id`, leaving the messages from others in the queue
+pub fn sys_receive_from(id: u32) -> Result<Message, SyscallError> {
+    receive_from(id, ipc::NO_WAIT)
+}
+
+// wait for a message from `id`, for `timeout` microseconds or forever if None
+pub fn sys_receive_from_blocking(id: u32, timeout: Option<u32>) -> Result<Message, SyscallError> {
+    receive_from(id, timeout.unwrap_or(ipc::WAIT_FOREVER))
+}
+
+fn send_buf(id: u32, buf: &[u8], timeout: u32) -> Result<(), SyscallError> {
+    let r = syscall_4(
+        number::SYS_SEND_BUF,
+        id,
+        buf.as_ptr() as u32,
+        buf.len() as u32,
+        timeout,
+    ) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(())
+    }
+}
+
+// send the bytes of buf (at most ipc::MAX_MESSAGE_LENGTH) as one message
+pub fn sys_send_buf(id: u32, buf: &[u8]) -> Result<(), SyscallError> {
+    send_buf(id, buf, ipc::NO_WAIT)
+}
+
+pub fn sys_send_buf_blocking(
+    id: u32,
+    buf: &[u8],
+    timeout: Option<u32>,
+) -> Result<(), SyscallError> {
+    send_buf(id, buf, timeout.unwrap_or(ipc::WAIT_FOREVER))
+}
+
+fn receive_buf(buf: &mut [u8], timeout: u32) -> Result<(u32, usize), SyscallError> {
+    let mut len: u32 = 0;
+    let r = syscall_4(
+        number::SYS_RECEIVE_BUF,
+        buf.as_mut_ptr() as u32,
+        buf.len() as u32,
+        (&mut len) as *mut u32 as u32,
+        timeout,
+    ) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok((r as u32, len as usize))
+    }
+}
+
+// receive a message into buf, and return the id of the sender and the length.
+// fails with InvalidArguments if the message is longer than buf
+pub fn sys_receive_buf(buf: &mut [u8]) -> Result<(u32, usize), SyscallError> {
+    receive_buf(buf, ipc::NO_WAIT)
+}
+
+pub fn sys_receive_buf_blocking(
+    buf: &mut [u8],
+    timeout: Option<u32>,
+) -> Result<(u32, usize), SyscallError> {
+    receive_buf(buf, timeout.unwrap_or(ipc::WAIT_FOREVER))
+}
+
+// the number of messages which can be queued to the caller
+pub fn sys_set_queue_capacity(capacity: usize) -> Result<(), SyscallError> {
+    let r = syscall_1(number::SYS_SET_QUEUE_CAPACITY, capacity as u32) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(())
+    }
+}
+
+// send `data` to `id` and wait for its reply (see sys_reply_recv)
+pub fn sys_call(id: u32, data: u32) -> Result<u32, SyscallError> {
+    let result: u32;
+    let reply: u32;
+    unsafe {
+        asm!("
+            ecall
+        "
+        : "={x10}"(result), "={x11}"(reply)
+        : "{x10}"(number::SYS_CALL), "{x11}"(id), "{x12}"(data)
+        );
+    }
+    let r = result as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(reply)
+    }
+}
+
+// reply to the last sys_call received, and wait for the next message
+pub fn sys_reply_recv(reply: u32) -> Result<Message, SyscallError> {
+    let mut data_store: u32 = 0;
+    let r = syscall_2(
+        number::SYS_REPLY_RECV,
+        reply,
+        (&mut data_store) as *mut u32 as u32,
+    ) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(Message {
+            id: r as u32,
+            data: data_store,
+        })
+    }
+}
+
 pub fn sys_mmap(
     src_id: u32,
     src_addr: u32,
@@ -216,6 +439,17 @@ pub fn sys_mmap(
     }
 }
 
+// allow the process `peer` to map the page at `addr` by sys_mmap, with the permissions in
+// `perm`. the empty permission revokes the grant
+pub fn sys_grant(peer: u32, addr: u32, perm: perm::Perm) -> Result<(), SyscallError> {
+    let r = syscall_3(number::SYS_GRANT, peer, addr, perm.bits()) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(())
+    }
+}
+
 pub fn sys_alloc(addr: Option<u32>, size: u32, perm: perm::Perm) -> Result<u32, SyscallError> {
     let addr_raw = match addr {
         Some(x) => x,
@@ -237,3 +471,87 @@ pub fn sys_free(addr: u32, size: u32) -> Result<(), SyscallError> {
         Ok(())
     }
 }
+
+pub fn sys_unmap(addr: u32, size: u32) -> Result<(), SyscallError> {
+    let r = syscall_2(number::SYS_UNMAP, addr, size) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn sys_open(path: &str) -> Result<u32, SyscallError> {
+    let r = syscall_2(
+        number::SYS_OPEN,
+        path.as_bytes().as_ptr() as u32,
+        path.len() as u32,
+    ) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(r as u32)
+    }
+}
+
+pub fn sys_close(fd: u32) -> Result<(), SyscallError> {
+    let r = syscall_1(number::SYS_CLOSE, fd) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn sys_read(fd: u32, buf: &mut [u8]) -> Result<usize, SyscallError> {
+    let r = syscall_3(
+        number::SYS_READ,
+        fd,
+        buf.as_mut_ptr() as u32,
+        buf.len() as u32,
+    ) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(r as usize)
+    }
+}
+
+pub fn sys_write(fd: u32, buf: &[u8]) -> Result<usize, SyscallError> {
+    let r = syscall_3(number::SYS_WRITE, fd, buf.as_ptr() as u32, buf.len() as u32) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(r as usize)
+    }
+}
+
+pub fn sys_lseek(fd: u32, offset: i32, whence: file::Whence) -> Result<u32, SyscallError> {
+    let r = syscall_3(number::SYS_LSEEK, fd, offset as u32, whence.to_u32()) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(r as u32)
+    }
+}
+
+// create a pipe. the fds of its read end and write end are returned
+pub fn sys_pipe() -> Result<(u32, u32), SyscallError> {
+    let mut fds = [0u32; 2];
+    let r = syscall_1(number::SYS_PIPE, fds.as_mut_ptr() as u32) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok((fds[0], fds[1]))
+    }
+}
+
+// make new_fd refer to what old_fd does. new_fd is closed first if it is open
+pub fn sys_dup2(old_fd: u32, new_fd: u32) -> Result<u32, SyscallError> {
+    let r = syscall_2(number::SYS_DUP2, old_fd, new_fd) as i32;
+    if r < 0 {
+        Err(SyscallError::from_syscall_result(r))
+    } else {
+        Ok(r as u32)
+    }
+}
@@ -1,10 +1,18 @@
-use syscall;
 use core::fmt::Write;
+use osmium_syscall::file;
+use syscall;
 
 struct UART;
 impl Write for UART {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
-        syscall::sys_write(s.as_bytes(), s.len());
+        // a pipe may take only a part of the bytes
+        let mut bytes = s.as_bytes();
+        while bytes.len() > 0 {
+            match syscall::sys_write(file::STDOUT, bytes) {
+                Ok(n) if n > 0 => bytes = &bytes[n..],
+                _ => break,
+            }
+        }
         Ok(())
     }
 }
@@ -30,7 +38,7 @@ pub fn buffered_readline(buffer: &mut [u8]) -> (usize, bool) {
     let l = buffer.len();
     let mut buf = [0u8; 1];
     for i in 0..l {
-        syscall::sys_read(&mut buf, 1);
+        let _ = syscall::sys_read(file::STDIN, &mut buf);
         if buf[0] == b'\n' {
             return (i, true);
         }
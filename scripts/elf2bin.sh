@@ -1,3 +1,4 @@
+python3 kernel/embed_symbols.py kernel/bin/osmium
 tools/bin/elf2bin kernel/bin/osmium bin/osmium.bin
 tools/bin/elf2bin bootloader/bin/bootloader bootloader/bin/bootloader.bin
 
@@ -0,0 +1,53 @@
+#!/usr/bin/env python3
+# take the core files which the kernel printed to the console (STREAM_TO_UART in
+# kernel/src/coredump.rs) out of a log, and write them to core.<pid>
+#
+#   ./scripts/run.sh | tee console.log
+#   python3 scripts/extract_core.py console.log
+
+import re
+import sys
+
+BEGIN = re.compile(r'-----BEGIN CORE (\d+)-----')
+END = re.compile(r'-----END CORE (\d+)-----')
+
+
+def extract(lines):
+    pid = None
+    data = []
+    for line in lines:
+        line = line.strip()
+        m = BEGIN.fullmatch(line)
+        if m:
+            pid, data = m.group(1), []
+            continue
+        if pid is None:
+            continue
+        m = END.fullmatch(line)
+        if m:
+            if m.group(1) == pid:
+                yield pid, bytes.fromhex(''.join(data))
+            pid = None
+            continue
+        data.append(line)
+
+
+def main():
+    if len(sys.argv) != 2:
+        print('usage: {} <console log>'.format(sys.argv[0]), file=sys.stderr)
+        sys.exit(1)
+    with open(sys.argv[1], errors='replace') as f:
+        cores = list(extract(f))
+    if not cores:
+        print('no core is found', file=sys.stderr)
+        sys.exit(1)
+    # a later core of the same pid replaces the former one, as on the disk
+    for pid, core in cores:
+        name = 'core.{}'.format(pid)
+        with open(name, 'wb') as f:
+            f.write(core)
+        print('{}: {} bytes'.format(name, len(core)))
+
+
+if __name__ == '__main__':
+    main()
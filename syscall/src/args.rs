@@ -0,0 +1,43 @@
+// Arguments of SYS_EXECVE
+//
+// argv and envp given to SYS_EXECVE are arrays of `Arg` terminated by an entry whose ptr
+// is 0. The kernel copies them to the top of the new user stack as follows.
+//
+//   ARGS_INFO_ADDR -> ArgsInfo { argc, argv, envc, envp }
+//                     strings (not terminated by '\0')
+//   argv           -> [Arg; argc]
+//   envp           -> [Arg; envc]
+//   initial sp     -> 16-byte aligned address below them
+
+// maximum number of entries in each of argv and envp
+pub const MAX_ARGS: usize = 32;
+// maximum total length of the strings in argv and envp
+pub const MAX_ARGS_SIZE: usize = 4096;
+// the end of the user stack - size of ArgsInfo
+pub const ARGS_INFO_ADDR: u32 = 0xe001_0000 - 16;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Arg {
+    pub ptr: u32,
+    pub len: u32,
+}
+
+impl Arg {
+    pub fn null() -> Arg {
+        Arg { ptr: 0, len: 0 }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.ptr == 0
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ArgsInfo {
+    pub argc: u32,
+    pub argv: u32,
+    pub envc: u32,
+    pub envp: u32,
+}
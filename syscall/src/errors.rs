@@ -14,6 +14,12 @@ pub enum SyscallError {
     QueueIsFull,
     PermissionDenied,
     InvalidAlignment,
+    BadFileDescriptor,
+    TooManyFiles,
+    BadAddress,
+    UnsupportedExecutable,
+    TimedOut,
+    BrokenPipe,
 }
 
 impl SyscallError {
@@ -31,6 +37,12 @@ impl SyscallError {
             SyscallError::QueueIsFull => -10,
             SyscallError::PermissionDenied => -11,
             SyscallError::InvalidAlignment => -12,
+            SyscallError::BadFileDescriptor => -13,
+            SyscallError::TooManyFiles => -14,
+            SyscallError::BadAddress => -15,
+            SyscallError::UnsupportedExecutable => -16,
+            SyscallError::TimedOut => -17,
+            SyscallError::BrokenPipe => -18,
         }
     }
 
@@ -48,6 +60,12 @@ impl SyscallError {
             -10 => SyscallError::QueueIsFull,
             -11 => SyscallError::PermissionDenied,
             -12 => SyscallError::InvalidAlignment,
+            -13 => SyscallError::BadFileDescriptor,
+            -14 => SyscallError::TooManyFiles,
+            -15 => SyscallError::BadAddress,
+            -16 => SyscallError::UnsupportedExecutable,
+            -17 => SyscallError::TimedOut,
+            -18 => SyscallError::BrokenPipe,
             _ => SyscallError::Unknown,
         }
     }
@@ -74,6 +92,12 @@ impl SyscallError {
             SyscallError::QueueIsFull => "Queue is full",
             SyscallError::PermissionDenied => "Permission denied",
             SyscallError::InvalidAlignment => "Invalid alignment",
+            SyscallError::BadFileDescriptor => "Bad file descriptor",
+            SyscallError::TooManyFiles => "Too many open files",
+            SyscallError::BadAddress => "Bad address",
+            SyscallError::UnsupportedExecutable => "Unsupported executable",
+            SyscallError::TimedOut => "Timed out",
+            SyscallError::BrokenPipe => "Broken pipe",
         }
     }
 }
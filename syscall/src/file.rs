@@ -0,0 +1,29 @@
+pub const STDIN: u32 = 0;
+pub const STDOUT: u32 = 1;
+pub const STDERR: u32 = 2;
+
+#[derive(Debug, Copy, Clone)]
+pub enum Whence {
+    Set,
+    Current,
+    End,
+}
+
+impl Whence {
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            Whence::Set => 0,
+            Whence::Current => 1,
+            Whence::End => 2,
+        }
+    }
+
+    pub fn from_u32(x: u32) -> Option<Whence> {
+        match x {
+            0 => Some(Whence::Set),
+            1 => Some(Whence::Current),
+            2 => Some(Whence::End),
+            _ => None,
+        }
+    }
+}
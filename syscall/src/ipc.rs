@@ -0,0 +1,12 @@
+// timeouts of SYS_SEND and SYS_RECEIVE, in microseconds.
+// return QueueIsFull or QueueIsEmpty immediately
+pub const NO_WAIT: u32 = 0;
+// block until the message is sent or received
+pub const WAIT_FOREVER: u32 = 0xffff_ffff;
+
+// the maximum length of a message sent by SYS_SEND_BUF
+pub const MAX_MESSAGE_LENGTH: usize = 4096;
+
+// the number of messages which a process can hold. see SYS_SET_QUEUE_CAPACITY
+pub const DEFAULT_QUEUE_CAPACITY: usize = 8;
+pub const MAX_QUEUE_CAPACITY: usize = 32;
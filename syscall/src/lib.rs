@@ -4,6 +4,10 @@
 #[macro_use]
 extern crate bitflags;
 
+pub mod args;
 pub mod errors;
+pub mod file;
+pub mod ipc;
 pub mod number;
 pub mod perm;
+pub mod process;
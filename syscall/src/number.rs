@@ -12,3 +12,20 @@ pub const SYS_SEND: u32 = 10;
 pub const SYS_RECEIVE: u32 = 11;
 pub const SYS_ALLOC: u32 = 12;
 pub const SYS_FREE: u32 = 13;
+pub const SYS_OPEN: u32 = 14;
+pub const SYS_CLOSE: u32 = 15;
+pub const SYS_READ: u32 = 16;
+pub const SYS_WRITE: u32 = 17;
+pub const SYS_LSEEK: u32 = 18;
+pub const SYS_WAIT: u32 = 19;
+pub const SYS_SET_PRIORITY: u32 = 20;
+pub const SYS_SLEEP: u32 = 21;
+pub const SYS_SEND_BUF: u32 = 22;
+pub const SYS_RECEIVE_BUF: u32 = 23;
+pub const SYS_SET_QUEUE_CAPACITY: u32 = 24;
+pub const SYS_CALL: u32 = 25;
+pub const SYS_REPLY_RECV: u32 = 26;
+pub const SYS_RECEIVE_FROM: u32 = 27;
+pub const SYS_PIPE: u32 = 28;
+pub const SYS_DUP2: u32 = 29;
+pub const SYS_GRANT: u32 = 30;
@@ -0,0 +1,36 @@
+// passed to SYS_WAIT to wait for any child
+pub const WAIT_ANY: u32 = 0xffff_ffff;
+
+// priorities of processes. a process with a smaller value is scheduled first (at most 32)
+pub const N_PRIORITIES: usize = 16;
+pub const DEFAULT_PRIORITY: u32 = 8;
+// passed to SYS_SET_PRIORITY to change the priority of the caller itself
+pub const CURRENT_PROCESS: u32 = 0xffff_ffff;
+
+// written to the buffer given to SYS_WAIT
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ExitStatus {
+    // the argument of SYS_EXIT, or the exception code if the process was killed
+    pub code: u32,
+    pub killed: u32,
+}
+
+impl ExitStatus {
+    pub fn exited(code: u32) -> ExitStatus {
+        ExitStatus { code, killed: 0 }
+    }
+
+    pub fn killed_by_exception(code: u32) -> ExitStatus {
+        ExitStatus { code, killed: 1 }
+    }
+
+    // exception code if the process was killed by an exception
+    pub fn killed_by(&self) -> Option<u32> {
+        if self.killed != 0 {
+            Some(self.code)
+        } else {
+            None
+        }
+    }
+}